@@ -0,0 +1,55 @@
+//! Insert points into a 2D histogram with the x values in ascending order,
+//! as with timestamps, and with the same points shuffled.
+//!
+//! Run with `cargo bench --bench histogram_2d`.
+
+extern crate dyn_histogram;
+
+use dyn_histogram::Histogram2d;
+use std::time::{Duration, Instant};
+
+/// Points along an ascending x axis with y values from xorshift64*, the
+/// same on every run
+fn ascending(n: usize) -> Vec<(f64, f64, u32)> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..n)
+        .map(|i| {
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            let bits = state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11;
+            (i as f64, bits as f64 / (1u64 << 53) as f64, 1)
+        })
+        .collect()
+}
+
+/// Fisher-Yates shuffle driven by the y values, which are random already
+fn shuffle(points: &mut [(f64, f64, u32)]) {
+    for i in (1..points.len()).rev() {
+        let j = (points[i].1 * (i + 1) as f64) as usize;
+        points.swap(i, j.min(i));
+    }
+}
+
+fn time(n_bins: usize, points: &[(f64, f64, u32)]) -> Duration {
+    let start = Instant::now();
+    let mut h = Histogram2d::new(n_bins);
+    h.insert_iter(points);
+    start.elapsed()
+}
+
+fn main() {
+    for &n_bins in &[100, 300, 1000] {
+        let mut points = ascending(5000);
+        let ascending = time(n_bins, &points);
+        shuffle(&mut points);
+        let shuffled = time(n_bins, &points);
+        println!(
+            "{:>5} bins, {} points: ascending {:>9.2?}, shuffled {:>9.2?}",
+            n_bins,
+            points.len(),
+            ascending,
+            shuffled
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Compare `insert_batch` with repeated `insert`, on uniformly distributed
+//! points and on the same points in ascending order.
+//!
+//! Run with `cargo bench --bench insert_batch`.
+
+extern crate dyn_histogram;
+
+use dyn_histogram::{DynamicHistogram, SimpleVecHistogram};
+use std::time::{Duration, Instant};
+
+/// Uniform points in [0, 1) from xorshift64*, the same on every run
+fn uniform(n: usize) -> Vec<(f64, u32)> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..n)
+        .map(|_| {
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            let bits = state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11;
+            (bits as f64 / (1u64 << 53) as f64, 1)
+        })
+        .collect()
+}
+
+fn time(f: impl FnOnce()) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn compare(order: &str, n_bins: usize, points: &[(f64, u32)]) {
+    let insert = time(|| {
+        let mut h = SimpleVecHistogram::<f64, u32>::new(n_bins);
+        for &(value, count) in points {
+            h.insert(value, count);
+        }
+    });
+    let insert_batch = time(|| {
+        let mut h = SimpleVecHistogram::<f64, u32>::new(n_bins);
+        h.insert_batch(points.iter().cloned());
+    });
+    println!(
+        "{:>9} {:>5} bins, {:>7} points: insert {:>9.2?}, insert_batch {:>9.2?}",
+        order,
+        n_bins,
+        points.len(),
+        insert,
+        insert_batch
+    );
+}
+
+fn main() {
+    for &(n_bins, n_points) in &[
+        (100, 1_000_000),
+        (1000, 1_000_000),
+        (2000, 1_000_000),
+        (5000, 200_000),
+    ] {
+        let mut points = uniform(n_points);
+        compare("uniform", n_bins, &points);
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        compare("ascending", n_bins, &points);
+    }
+}
@@ -0,0 +1,127 @@
+use num::One;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use traits::{DynamicHistogram, EmptyClone, MergeIter, MergeRef};
+
+/// Source of the stripe of each thread, handed out round robin
+static NEXT_STRIPE: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static STRIPE: usize = NEXT_STRIPE.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Histogram that many threads can record into at once.
+///
+/// Points are inserted into one of several stripes, each a histogram
+/// behind its own lock, so that threads rarely wait for each other. Each
+/// thread sticks to one stripe. Reads merge all stripes into one
+/// histogram.
+#[derive(Debug)]
+pub struct ConcurrentHistogram<H> {
+    stripes: Vec<Mutex<H>>,
+}
+
+impl<H: EmptyClone> ConcurrentHistogram<H> {
+    /// Instantiate a histogram with one stripe per available CPU, each an
+    /// empty clone of `template`
+    pub fn new(template: H) -> Self {
+        let n_stripes = thread::available_parallelism().map_or(1, usize::from);
+        Self::with_stripes(template, n_stripes)
+    }
+
+    /// Instantiate a histogram with the given number of stripes, each an
+    /// empty clone of `template`.
+    ///
+    /// Panics if `n_stripes` is zero.
+    pub fn with_stripes(template: H, n_stripes: usize) -> Self {
+        assert!(n_stripes > 0, "histogram must have at least one stripe");
+        ConcurrentHistogram {
+            stripes: (0..n_stripes)
+                .map(|_| Mutex::new(template.empty_clone()))
+                .collect(),
+        }
+    }
+
+    /// Lock the stripe of the calling thread
+    fn stripe(&self) -> MutexGuard<'_, H> {
+        let index = STRIPE.with(|stripe| *stripe % self.stripes.len());
+        lock(&self.stripes[index])
+    }
+
+    /// Record a single data point
+    pub fn record<V, C: One>(&self, value: V)
+    where
+        H: DynamicHistogram<V, C>,
+    {
+        self.stripe().insert(value, C::one())
+    }
+
+    /// Record a data point with the given count
+    pub fn record_n<V, C>(&self, value: V, count: C)
+    where
+        H: DynamicHistogram<V, C>,
+    {
+        self.stripe().insert(value, count)
+    }
+
+    /// Merge all stripes into one histogram.
+    ///
+    /// All stripes are locked at once while merging, so the snapshot
+    /// contains every point recorded before the call and none recorded
+    /// after it returns.
+    pub fn snapshot(&self) -> H
+    where
+        H: MergeRef,
+    {
+        let stripes = self.stripes.iter().map(lock).collect::<Vec<_>>();
+        stripes.iter().map(|stripe| &**stripe).merge().unwrap()
+    }
+}
+
+/// A thread that panicked while inserting cannot leave a stripe in a
+/// state that is unsafe to read, so poisoned locks are ignored
+fn lock<H>(stripe: &Mutex<H>) -> MutexGuard<'_, H> {
+    stripe.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use d2tree_histogram::Histogram;
+    use simple_vec_histogram::SimpleVecHistogram;
+    use traits::{Median, Quantile};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn record_from_threads() {
+        assert_send_sync::<ConcurrentHistogram<SimpleVecHistogram<f64, u32>>>();
+        assert_send_sync::<ConcurrentHistogram<Histogram<f64, u32>>>();
+
+        let histogram = ConcurrentHistogram::with_stripes(SimpleVecHistogram::new(16), 3);
+        thread::scope(|scope| {
+            for t in 0..8 {
+                let histogram = &histogram;
+                scope.spawn(move || {
+                    for i in 0..100 {
+                        histogram.record(f64::from(t * 100 + i));
+                    }
+                });
+            }
+        });
+        histogram.record_n(-1., 10u32);
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count(), 810);
+        assert_eq!(snapshot.quantile(0.), Some(-1.));
+        assert_eq!(snapshot.quantile(1.), Some(799.));
+        assert!(snapshot.median().is_some());
+    }
+
+    #[test]
+    fn empty_snapshot() {
+        let histogram = ConcurrentHistogram::new(Histogram::<f64, u32>::new(8));
+        assert_eq!(histogram.snapshot().count(), 0);
+    }
+}
@@ -0,0 +1,1076 @@
+use error::HistogramError;
+use non_finite::{NonFinite, NonFinitePolicy};
+use num::traits::NumAssign;
+#[cfg(feature = "serde")]
+use num::Zero;
+use num::{Bounded, Float, NumCast, ToPrimitive};
+use ordered_float::OrderedFloat;
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sets::HistogramSetItem;
+use std::cmp::Ordering;
+use std::collections::binary_heap::BinaryHeap;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Bound::{Excluded, Included, Unbounded};
+use traits::fallible::{check_count, check_total};
+use traits::{
+    BinSummary, BinaryEncoding, DynamicHistogram, EmptyClone, GapDistance, IterBins, Merge,
+    MergeRef, MergeStrategy, Moments, RunningMoments, TryInsert, TryMerge,
+};
+use wire::{self, DecodeError, WireNumber};
+
+/// Histogram storing its bins in a `BTreeMap`, with a heap of bin distances
+/// to find the closest pair of bins in O(log n) when merging
+#[derive(Clone)]
+pub struct Histogram<L: Float, C: NumAssign + Copy, S = GapDistance> {
+    bins: BTreeMap<BinAddress<L>, BinData<L, C>>,
+    distances: BinaryHeap<BinDistance<L, C>>,
+    n_bins: usize,
+    total: C,
+    moments: RunningMoments<L>,
+    strategy: S,
+    non_finite: NonFinite<L, C>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SerializableHistogram<L, C> {
+    n_bins: usize,
+    bins: Vec<(L, L, BinData<L, C>)>,
+    moments: RunningMoments<L>,
+    non_finite: NonFinite<L, C>,
+}
+
+impl<L: Float, C: NumAssign + Copy + ToPrimitive> Histogram<L, C> {
+    /// Instantiate a histogram with the given number of maximum bins,
+    /// merging the bins with the smallest gap first
+    pub fn new(n_bins: usize) -> Self {
+        Self::with_strategy(n_bins, GapDistance)
+    }
+}
+
+impl<L, C, S> DynamicHistogram<L, C> for Histogram<L, C, S>
+where
+    L: Float,
+    C: NumAssign + Copy + ToPrimitive,
+    S: MergeStrategy<L, C> + Default,
+{
+    /// Type of a bin in this histogram
+    type Bin = (BinAddress<L>, BinData<L, C>);
+
+    /// Instantiate a histogram with the given number of maximum bins
+    fn new(n_bins: usize) -> Self {
+        Self::with_strategy(n_bins, S::default())
+    }
+
+    /// Insert a new data point into this histogram
+    fn insert(&mut self, y: L, count: C) {
+        let weight = match count.to_f64() {
+            Some(weight) if weight > 0. => weight,
+            _ => return,
+        };
+        // like a weight that is not positive, a count that can't be
+        // represented as a value is ignored
+        let value_weight = match value_weight::<L, C>(count) {
+            Some(value_weight) => value_weight,
+            None => return,
+        };
+        let y = match self.non_finite.admit(y, count) {
+            Some(y) => y,
+            None => return,
+        };
+        self.total += count;
+        self.moments.insert(y, weight);
+        let (weighted, weighted_sq) = (y * value_weight, y * y * value_weight);
+        let new_bin_address = BinAddress::init(y);
+
+        let existing = self
+            .bins
+            .range((Unbounded, Included(&new_bin_address)))
+            .next_back()
+            .map(|(addr, _)| addr)
+            .filter(|addr| addr.right >= new_bin_address.right)
+            .cloned();
+        let (address, created) = match existing {
+            Some(addr) => {
+                let data = self.bins.get_mut(&addr).unwrap();
+                data.count += count;
+                data.sum = data.sum + weighted;
+                data.sum_sq = data.sum_sq + weighted_sq;
+                (addr, false)
+            }
+            None => {
+                let data = BinData::new(count, weighted, weighted_sq);
+                self.bins.insert(new_bin_address.clone(), data);
+                (new_bin_address, true)
+            }
+        };
+
+        // distances are only needed once the histogram is full, so they are
+        // built when it fills up and kept up to date from then on
+        match self.bins.len().cmp(&self.n_bins) {
+            Ordering::Less => {}
+            Ordering::Equal if created => self.rebuild_distances(),
+            // distances to the changed bin are out of date now
+            _ => self.push_distances(&address),
+        }
+        self.shrink_to_fit();
+    }
+
+    /// Count the total number of data points in this histogram (over all bins)
+    fn count(&self) -> C {
+        self.total
+    }
+}
+
+impl<L: Float, C: NumAssign + Copy, S> IterBins for Histogram<L, C, S> {
+    type Value = L;
+    type Count = C;
+
+    fn iter_bins(&self) -> impl Iterator<Item = BinSummary<L, C>> + '_ {
+        self.bins.iter().map(|(addr, data)| summary(addr, data))
+    }
+}
+
+impl<L: Float, C: NumAssign + Copy, S> Moments<L> for Histogram<L, C, S> {
+    fn moments(&self) -> &RunningMoments<L> {
+        &self.moments
+    }
+}
+
+impl<L: Float, C: NumAssign + Copy, S> From<Histogram<L, C, S>> for SerializableHistogram<L, C> {
+    /// Turn this item into a serializable version of itself
+    fn from(hist: Histogram<L, C, S>) -> Self {
+        let n_bins = hist.n_bins;
+        let moments = hist.moments;
+        let non_finite = hist.non_finite;
+        let bins = hist
+            .bins
+            .into_iter()
+            .map(|(address, data)| (address.left.into_inner(), address.right.into_inner(), data))
+            .collect();
+        SerializableHistogram {
+            n_bins,
+            bins,
+            moments,
+            non_finite,
+        }
+    }
+}
+
+impl<L, C, S> From<SerializableHistogram<L, C>> for Histogram<L, C, S>
+where
+    L: Float,
+    C: NumAssign + Copy,
+    S: MergeStrategy<L, C> + Default,
+{
+    /// Recover a item from its serializable representation
+    fn from(ser: SerializableHistogram<L, C>) -> Self {
+        let mut histogram = Histogram::from_parts(ser.n_bins, S::default(), ser.moments, ser.bins);
+        histogram.non_finite = ser.non_finite;
+        histogram
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<L, C, S> Serialize for Histogram<L, C, S>
+where
+    L: Float + Serialize,
+    C: NumAssign + Copy + Serialize,
+    S: Serialize,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        #[derive(Serialize)]
+        struct Raw<'a, L: 'a, C: 'a, S: 'a> {
+            n_bins: usize,
+            bins: Vec<(L, L, &'a BinData<L, C>)>,
+            moments: &'a RunningMoments<L>,
+            strategy: &'a S,
+            non_finite: &'a NonFinite<L, C>,
+        }
+
+        Raw {
+            n_bins: self.n_bins,
+            bins: self
+                .bins
+                .iter()
+                .map(|(addr, data)| (addr.left.into_inner(), addr.right.into_inner(), data))
+                .collect(),
+            moments: &self.moments,
+            strategy: &self.strategy,
+            non_finite: &self.non_finite,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, L, C, S> Deserialize<'de> for Histogram<L, C, S>
+where
+    L: Float + Deserialize<'de>,
+    C: NumAssign + Copy + PartialOrd + Bounded + ToPrimitive + Deserialize<'de>,
+    S: MergeStrategy<L, C> + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Raw<L, C: Zero, S> {
+            n_bins: usize,
+            bins: Vec<(L, L, BinData<L, C>)>,
+            moments: RunningMoments<L>,
+            strategy: S,
+            #[serde(default = "NonFinite::default")]
+            non_finite: NonFinite<L, C>,
+        }
+
+        let raw = Raw::<L, C, S>::deserialize(deserializer)?;
+        let bounds = raw.bins.iter().map(|b| (b.0, b.1, b.2.count));
+        ::validation::check_bins(bounds, raw.n_bins).map_err(de::Error::custom)?;
+        let mut histogram = Histogram::from_parts(raw.n_bins, raw.strategy, raw.moments, raw.bins);
+        histogram.non_finite = raw.non_finite;
+        Ok(histogram)
+    }
+}
+
+impl<L, C, S> BinaryEncoding for Histogram<L, C, S>
+where
+    L: Float + WireNumber,
+    C: NumAssign + WireNumber + PartialOrd + Bounded + ToPrimitive,
+    S: MergeStrategy<L, C> + Default,
+{
+    fn to_bytes(&self) -> Vec<u8> {
+        let bins = self.iter_bins().collect::<Vec<_>>();
+        wire::encode(self.n_bins, &bins, &self.moments, self.non_finite.counts())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let payload = wire::decode(bytes)?;
+        let bins = payload
+            .bins
+            .into_iter()
+            .map(|b| (b.left, b.right, BinData::new(b.count, b.sum, b.sum_sq)));
+        let mut histogram =
+            Histogram::from_parts(payload.n_bins, S::default(), payload.moments, bins);
+        if let Some(counts) = payload.non_finite {
+            histogram.non_finite.set_counts(counts);
+        }
+        Ok(histogram)
+    }
+}
+
+impl<L: Float, C: NumAssign + Copy, S: Clone> EmptyClone for Histogram<L, C, S> {
+    fn empty_clone(&self) -> Self {
+        let mut histogram = Self::with_strategy(self.n_bins, self.strategy.clone());
+        histogram.non_finite = self.non_finite.empty_clone();
+        histogram
+    }
+}
+
+impl<L, C, S> HistogramSetItem for Histogram<L, C, S>
+where
+    L: Float,
+    C: NumAssign + Copy,
+    S: MergeStrategy<L, C> + Clone + Default,
+{
+    type Serializable = SerializableHistogram<L, C>;
+}
+
+impl<L, C, S> TryInsert<L, C> for Histogram<L, C, S>
+where
+    L: Float,
+    C: NumAssign + Copy + PartialOrd + Bounded + ToPrimitive,
+    S: MergeStrategy<L, C> + Default,
+{
+    fn try_insert(&mut self, y: L, count: C) -> Result<(), HistogramError> {
+        check_count(count, self.total, self.n_bins)?;
+        self.non_finite.check(y, count)?;
+        value_weight::<L, C>(count).ok_or(HistogramError::CastFailed)?;
+        self.insert(y, count);
+        Ok(())
+    }
+}
+
+impl<L, C, S> TryMerge for Histogram<L, C, S>
+where
+    L: Float,
+    C: NumAssign + Copy + PartialOrd + Bounded,
+    S: MergeStrategy<L, C>,
+{
+    fn try_merge(&mut self, other: Self) -> Result<(), HistogramError> {
+        if self.n_bins == 0 {
+            return Err(HistogramError::ZeroCapacity);
+        }
+        check_total(self.total, other.total)?;
+        self.non_finite.check_merge(&other.non_finite)?;
+        self.merge(other);
+        Ok(())
+    }
+}
+
+impl<L: Float, C: NumAssign + Copy, S: MergeStrategy<L, C>> Merge for Histogram<L, C, S> {
+    fn merge(&mut self, other: Self) {
+        self.total += other.total;
+        self.moments.merge(&other.moments);
+        self.non_finite.merge(&other.non_finite);
+        for (new_addr, new_data) in other.bins {
+            self.bins
+                .entry(new_addr)
+                .and_modify(|bin| bin.merge(&new_data))
+                .or_insert(new_data);
+        }
+        self.rebuild_distances();
+        self.shrink_to_fit();
+    }
+}
+
+impl<L: Float, C: NumAssign + Copy, S: MergeStrategy<L, C>> MergeRef for Histogram<L, C, S> {
+    fn merge_ref(&mut self, other: &Self) {
+        self.total += other.total;
+        self.moments.merge(&other.moments);
+        self.non_finite.merge(&other.non_finite);
+        for (new_addr, new_data) in &other.bins {
+            self.bins
+                .entry(new_addr.clone())
+                .and_modify(|bin| bin.merge(new_data))
+                .or_insert(*new_data);
+        }
+        self.rebuild_distances();
+        self.shrink_to_fit();
+    }
+}
+
+impl<L: Float, C: NumAssign + Copy, S> Histogram<L, C, S> {
+    /// Instantiate a histogram with the given number of maximum bins,
+    /// which uses `strategy` to decide which bins to merge
+    pub fn with_strategy(n_bins: usize, strategy: S) -> Self {
+        Histogram {
+            n_bins,
+            distances: BinaryHeap::new(),
+            bins: BTreeMap::new(),
+            total: C::zero(),
+            moments: RunningMoments::default(),
+            strategy,
+            non_finite: NonFinite::default(),
+        }
+    }
+
+    /// Use `policy` for NaN and infinite values instead of rejecting them.
+    /// Panics if the bounds of a `Clamp` policy are not finite or `min` is
+    /// greater than `max`.
+    pub fn with_non_finite_policy(mut self, policy: NonFinitePolicy<L>) -> Self {
+        self.non_finite = NonFinite::new(policy);
+        self
+    }
+
+    /// Policy for NaN and infinite values, with the counts of those that
+    /// were left out of the bins and so of `count`
+    pub fn non_finite(&self) -> &NonFinite<L, C> {
+        &self.non_finite
+    }
+
+    /// Assemble a histogram from bins given by their boundaries, merging
+    /// bins with identical boundaries
+    fn from_parts<I>(n_bins: usize, strategy: S, moments: RunningMoments<L>, bins: I) -> Self
+    where
+        S: MergeStrategy<L, C>,
+        I: IntoIterator<Item = (L, L, BinData<L, C>)>,
+    {
+        let mut histogram = Histogram::with_strategy(n_bins, strategy);
+        histogram.moments = moments;
+        for (left, right, data) in bins {
+            histogram.total += data.count;
+            histogram
+                .bins
+                .entry(BinAddress::new(left, right))
+                .and_modify(|bin| bin.merge(&data))
+                .or_insert(data);
+        }
+        histogram.rebuild_distances();
+        histogram
+    }
+
+    fn shrink_to_fit(&mut self)
+    where
+        S: MergeStrategy<L, C>,
+    {
+        while self.bins.len() > self.n_bins {
+            // find two closest together bins
+            let least_diff = match self.distances.pop() {
+                Some(least_diff) => least_diff,
+                None => {
+                    self.rebuild_distances();
+                    if self.distances.is_empty() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            // there may be "out of date" distances on the heap if one of the bins
+            // was merged or changed, or a new bin was inserted between them
+            let adjacent = self
+                .bins
+                .range((Excluded(&least_diff.left), Excluded(&least_diff.right)))
+                .next()
+                .is_none();
+            let current = |addr, count| {
+                self.bins
+                    .get(addr)
+                    .is_some_and(|data: &BinData<L, C>| data.count == count)
+            };
+            if !adjacent
+                || !current(&least_diff.left, least_diff.left_count)
+                || !current(&least_diff.right, least_diff.right_count)
+            {
+                continue;
+            }
+
+            let mut merged_data = self.bins.remove(&least_diff.left).unwrap();
+            let data_r = self.bins.remove(&least_diff.right).unwrap();
+            let (mut merged_addr, addr_r) = (least_diff.left, least_diff.right);
+            merged_addr.merge(&addr_r);
+            merged_data.merge(&data_r);
+            self.bins.insert(merged_addr.clone(), merged_data);
+
+            // insert updated distances after merge
+            self.push_distances(&merged_addr);
+        }
+
+        // drop the out of date distances piling up from inserts and merges
+        if self.distances.len() > self.n_bins.saturating_mul(10) {
+            self.rebuild_distances();
+        }
+    }
+
+    /// Push the distances between the bin at `address` and its neighbours
+    fn push_distances(&mut self, address: &BinAddress<L>)
+    where
+        S: MergeStrategy<L, C>,
+    {
+        let data = &self.bins[address];
+        if let Some((before_addr, before_data)) =
+            self.bins.range((Unbounded, Excluded(address))).next_back()
+        {
+            let dist =
+                BinDistance::new(&self.strategy, (before_addr, before_data), (address, data));
+            self.distances.push(dist);
+        }
+        if let Some((after_addr, after_data)) =
+            self.bins.range((Excluded(address), Unbounded)).next()
+        {
+            let dist = BinDistance::new(&self.strategy, (address, data), (after_addr, after_data));
+            self.distances.push(dist);
+        }
+    }
+
+    fn rebuild_distances(&mut self)
+    where
+        S: MergeStrategy<L, C>,
+    {
+        self.distances.clear();
+        for (left, right) in self.bins.iter().zip(self.bins.iter().skip(1)) {
+            self.distances
+                .push(BinDistance::new(&self.strategy, left, right));
+        }
+    }
+
+    pub fn bins(&self) -> &BTreeMap<BinAddress<L>, BinData<L, C>> {
+        &self.bins
+    }
+}
+
+fn summary<L: Float, C: Copy>(addr: &BinAddress<L>, data: &BinData<L, C>) -> BinSummary<L, C> {
+    BinSummary {
+        left: addr.left.into_inner(),
+        right: addr.right.into_inner(),
+        count: data.count,
+        sum: data.sum,
+        sum_sq: data.sum_sq,
+    }
+}
+
+/// Weight of `count` points as a value, or `None` if the count can't be
+/// represented as one
+fn value_weight<L: Float, C: ToPrimitive>(count: C) -> Option<L> {
+    L::from(count)
+}
+
+impl<L: Float + fmt::Debug, C: fmt::Debug + NumAssign + Copy, S> fmt::Debug for Histogram<L, C, S> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "Bins:")?;
+        for (addr, bin) in &self.bins {
+            writeln!(
+                fmt,
+                "{:?}/{:?} -> {:?}",
+                addr.left.into_inner(),
+                addr.right.into_inner(),
+                bin
+            )?;
+        }
+        writeln!(fmt, "Distances:")?;
+        for dist in &self.distances {
+            writeln!(fmt, "{:?}", dist)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinAddress<L: Float> {
+    pub left: OrderedFloat<L>,
+    pub right: OrderedFloat<L>,
+}
+
+impl<L: Float> Eq for BinAddress<L> {}
+
+impl<L: Float> Ord for BinAddress<L> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let left_ord = self.left.cmp(&other.left);
+        let right_ord = self.right.cmp(&other.right);
+        left_ord.then(right_ord)
+    }
+}
+
+impl<L: Float> PartialOrd for BinAddress<L> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<L: Float> BinAddress<L> {
+    pub fn init(y: L) -> Self {
+        BinAddress {
+            left: y.into(),
+            right: y.into(),
+        }
+    }
+
+    pub fn new(left: L, right: L) -> Self {
+        BinAddress {
+            left: left.into(),
+            right: right.into(),
+        }
+    }
+
+    pub fn contains(&self, y: L) -> Ordering {
+        let y_ord = OrderedFloat::from(y);
+        if y_ord < self.left {
+            Ordering::Less
+        } else if y_ord > self.right {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    }
+
+    /// Merges this bin with another one, summing the number of points
+    /// and shifting the center of the bin to accomodate
+    pub fn merge(&mut self, other: &Self) {
+        self.left = OrderedFloat::min(self.left, other.left);
+        self.right = OrderedFloat::max(self.right, other.right);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BinData<L, C> {
+    count: C,
+    sum: L,
+    sum_sq: L,
+}
+
+impl<L: Float, C: NumAssign + Copy> BinData<L, C> {
+    pub fn init(y: L) -> Self {
+        BinData {
+            count: C::one(),
+            sum: y,
+            sum_sq: y * y,
+        }
+    }
+
+    pub fn new(count: C, sum: L, sum_sq: L) -> Self {
+        BinData { count, sum, sum_sq }
+    }
+
+    /// Merges this bin with another one, summing the number of points
+    /// and shifting the center of the bin to accomodate
+    pub fn merge(&mut self, other: &Self) {
+        self.sum = self.sum + other.sum;
+        self.sum_sq = self.sum_sq + other.sum_sq;
+        self.count += other.count;
+    }
+}
+
+pub trait PartialBinSum<L, C> {
+    /// Estimates an R-partial sum of this bin, where R is any number
+    /// between 0 and the number of samples contained in the bin
+    /// panics if R is greater than the count of items in the bin
+    fn partial_sum(&self, r: C) -> L;
+
+    /// Estimates an R-partial sum of this bin like `partial_sum`, but fails
+    /// if R is greater than the count of items in the bin, a count can't
+    /// be converted to the value type or the estimate is NaN. Bins with up
+    /// to two points are interpolated linearly.
+    fn try_partial_sum(&self, r: C) -> Result<L, HistogramError>;
+}
+
+impl<L: Float + NumCast, C: Copy + PartialOrd + ToPrimitive + NumAssign> PartialBinSum<L, C>
+    for (&BinAddress<L>, &BinData<L, C>)
+{
+    /// Estimates an R-partial sum of this bin, where R is any number
+    /// between 0 and the number of samples contained in the bin
+    /// panics if R is greater than the count of items in the bin
+    fn partial_sum(&self, r: C) -> L {
+        match self.try_partial_sum(r) {
+            Ok(sum) => sum,
+            Err(HistogramError::RankOutOfRange) => {
+                panic!("Attempt to calculate R-Partial sum where R > bin.count")
+            }
+            Err(err) => panic!("Failed to calculate R-Partial sum: {}", err),
+        }
+    }
+
+    fn try_partial_sum(&self, r: C) -> Result<L, HistogramError> {
+        let (addr, data) = self;
+        let cast = |count: C| L::from(count).ok_or(HistogramError::CastFailed);
+        if r == C::zero() {
+            Ok(L::zero())
+        } else if r < data.count {
+            let r_float = cast(r)?;
+            let count_float = cast(data.count)?;
+            let two = L::one() + L::one();
+            let sum = if count_float <= two {
+                // too few points to estimate their spacing
+                data.sum * r_float / count_float
+            } else {
+                let delta = (data.sum - *addr.right - count_float * *addr.left + *addr.left)
+                    / ((count_float - two) * (count_float - L::one()));
+                r_float * *addr.left + r_float * (r_float - L::one()) * delta
+            };
+            if sum.is_nan() {
+                Err(HistogramError::NotANumber)
+            } else {
+                Ok(sum)
+            }
+        } else if r == data.count {
+            Ok(data.sum)
+        } else {
+            Err(HistogramError::RankOutOfRange)
+        }
+    }
+}
+
+#[derive(Clone)]
+struct BinDistance<L: Float, C> {
+    pub left: BinAddress<L>,
+    pub right: BinAddress<L>,
+    pub left_count: C,
+    pub right_count: C,
+    pub distance: f64,
+}
+
+impl<L: Float, C: Copy> BinDistance<L, C> {
+    pub fn new<S: MergeStrategy<L, C>>(
+        strategy: &S,
+        (left, left_data): (&BinAddress<L>, &BinData<L, C>),
+        (right, right_data): (&BinAddress<L>, &BinData<L, C>),
+    ) -> Self {
+        let distance = strategy.cost(&summary(left, left_data), &summary(right, right_data));
+        BinDistance {
+            left: left.clone(),
+            right: right.clone(),
+            left_count: left_data.count,
+            right_count: right_data.count,
+            distance,
+        }
+    }
+}
+
+// Implement reverse ordering for BinDistance so it can be used in a max-heap
+
+impl<L: Float, C> PartialEq for BinDistance<L, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<L: Float, C> Eq for BinDistance<L, C> {}
+
+impl<L: Float, C> PartialOrd for BinDistance<L, C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<L: Float, C> BinDistance<L, C> {
+    /// Distance as a totally ordered number, with bins that cannot be
+    /// compared merged last
+    fn key(&self) -> f64 {
+        if self.distance.is_nan() {
+            f64::INFINITY
+        } else {
+            self.distance
+        }
+    }
+}
+
+impl<L: Float, C> Ord for BinDistance<L, C> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key().total_cmp(&self.key())
+    }
+}
+
+impl<L: Float + fmt::Debug, C> fmt::Debug for BinDistance<L, C> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{:?}/{:?} -> {:?} -> {:?}/{:?}",
+            self.left.left, self.left.right, self.distance, self.right.left, self.right.right
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_sum() {
+        let (addr, data) = (BinAddress::new(1., 3.), BinData::new(3u32, 6., 14.));
+        let bin = (&addr, &data);
+        assert_eq!(bin.try_partial_sum(1), Ok(1.));
+        assert_eq!(bin.try_partial_sum(3), Ok(6.));
+        assert_eq!(bin.partial_sum(3), 6.);
+        assert_eq!(bin.try_partial_sum(4), Err(HistogramError::RankOutOfRange));
+
+        // bins with one or two points have no spacing to estimate
+        let (addr, data) = (BinAddress::new(1., 3.), BinData::new(2u32, 4., 10.));
+        assert_eq!((&addr, &data).try_partial_sum(1), Ok(2.));
+        assert_eq!((&addr, &data).try_partial_sum(0), Ok(0.));
+        let (addr, data) = (BinAddress::init(2.), BinData::new(1u32, 2., 4.));
+        assert_eq!((&addr, &data).try_partial_sum(0), Ok(0.));
+        assert_eq!((&addr, &data).try_partial_sum(1), Ok(2.));
+        let (addr, data) = (BinAddress::new(1., 2.), BinData::new(1.5, 2., 3.));
+        assert_eq!((&addr, &data).try_partial_sum(0.75), Ok(1.));
+    }
+
+    #[test]
+    fn try_insert() {
+        use traits::{TryMedian, TryMerge};
+
+        let mut histogram = Histogram::new(2);
+        assert_eq!(
+            histogram.try_insert(f64::NAN, 1u16),
+            Err(HistogramError::NotANumber)
+        );
+        assert_eq!(histogram.try_insert(1., u16::MAX), Ok(()));
+        assert_eq!(histogram.try_insert(2., 1), Err(HistogramError::Overflow));
+        assert_eq!(histogram.try_median(), Ok(1.));
+        let copy = histogram.clone();
+        assert_eq!(histogram.try_merge(copy), Err(HistogramError::Overflow));
+        assert_eq!(
+            Histogram::<f64, u16>::new(0).try_insert(1., 1),
+            Err(HistogramError::ZeroCapacity)
+        );
+    }
+
+    #[test]
+    fn bounded_distances() {
+        let mut histogram = Histogram::new(100);
+        let mut full = Histogram::new(10);
+        for i in 0..10_000u32 {
+            histogram.insert((i % 5) as f64, 1u32);
+            full.insert((i % 50) as f64, 1u32);
+        }
+        assert_eq!(histogram.bins().len(), 5);
+        assert_eq!(full.bins().len(), 10);
+        assert!(histogram.distances.len() <= 1000);
+        assert!(full.distances.len() <= 100);
+    }
+
+    #[test]
+    fn insert() {
+        let mut histogram = Histogram::new(3);
+        let items = vec![1., 1., 2., 3.5, 2.1, 3.6];
+        for i in items {
+            histogram.insert(i, 1);
+        }
+        assert_eq!(
+            histogram.bins().iter().collect::<Vec<_>>(),
+            vec![
+                (&BinAddress::new(1.0, 1.0), &BinData::new(2, 2.0, 2.0)),
+                (
+                    &BinAddress::new(2.0, 2.1),
+                    &BinData::new(2, 4.1, 2. * 2. + 2.1 * 2.1)
+                ),
+                (
+                    &BinAddress::new(3.5, 3.6),
+                    &BinData::new(2, 7.1, 3.5 * 3.5 + 3.6 * 3.6)
+                ),
+            ]
+        )
+    }
+
+    #[test]
+    fn merge() {
+        let mut h1 = Histogram::new(3);
+        vec![1., 1.5, 3., 4., 4.5, 6.]
+            .into_iter()
+            .for_each(|i| h1.insert(i, 1));
+
+        let mut h2 = Histogram::new(3);
+        vec![1.0, 7.0, 5.0]
+            .into_iter()
+            .for_each(|i| h2.insert(i, 1));
+        h1.merge_ref(&h2);
+        assert_eq!(h1.count(), 9);
+
+        assert_eq!(
+            h1.bins().iter().collect::<Vec<_>>(),
+            vec![
+                (&BinAddress::new(1.0, 1.5), &BinData::new(3, 3.5, 4.25)),
+                (&BinAddress::new(3.0, 5.0), &BinData::new(4, 16.5, 70.25)),
+                (&BinAddress::new(6.0, 7.0), &BinData::new(2, 13.0, 85.0)),
+            ]
+        )
+    }
+
+    #[test]
+    fn sum_upto() {
+        use traits::RangeCount;
+
+        let mut histogram = Histogram::new(3);
+        histogram.insert_iter(&[(1., 2u32), (2., 4), (4., 2)]);
+
+        assert_eq!(histogram.sum_upto(0.5), 0.);
+        assert_eq!(histogram.sum_upto(1.), 1.);
+        assert_eq!(histogram.sum_upto(1.5), 2.25);
+        assert_eq!(histogram.sum_upto(2.), 4.);
+        assert_eq!(histogram.sum_upto(4.), 8.);
+        assert_eq!(histogram.count_between(1.5, 2.), 1.75);
+        assert_eq!(histogram.count_between(2., 1.5), 0.);
+    }
+
+    #[test]
+    fn uniform() {
+        use traits::{RangeCount, Uniform};
+
+        let mut histogram = Histogram::new(3);
+        histogram.insert_iter(&[(1., 2u32), (2., 4), (4., 2)]);
+
+        assert_eq!(histogram.uniform(2), vec![2.]);
+        let points = histogram.uniform(4);
+        assert_eq!(points.len(), 3);
+        for (j, point) in points.into_iter().enumerate() {
+            let expected = 2. * (j + 1) as f64;
+            assert!((histogram.sum_upto(point) - expected).abs() < 1e-9);
+        }
+        assert!(histogram.uniform(1).is_empty());
+        assert!(Histogram::<f64, u32>::new(3).uniform(4).is_empty());
+    }
+
+    #[test]
+    fn merge_strategy() {
+        use traits::CountWeightedDistance;
+
+        let mut histogram = Histogram::with_strategy(3, CountWeightedDistance);
+        histogram.insert_iter(&[(0., 1u32), (10., 1), (11., 1)]);
+        // makes merging 10 and 11 expensive, although it was the cheapest pair
+        histogram.insert(11., 100);
+        histogram.insert(30., 1);
+
+        assert_eq!(
+            histogram.bins().keys().collect::<Vec<_>>(),
+            vec![
+                &BinAddress::new(0., 10.),
+                &BinAddress::new(11., 11.),
+                &BinAddress::new(30., 30.),
+            ]
+        );
+    }
+
+    #[test]
+    fn median() {
+        use traits::Median;
+
+        let mut histogram = Histogram::new(10);
+        for i in &[1., 2., 3., 4., 5., 6., 7., 8., 9.] {
+            histogram.insert(*i, 1u32);
+        }
+        assert_eq!(histogram.count(), 9);
+        assert_eq!(histogram.median(), Some(5.));
+    }
+
+    #[test]
+    fn weighted() {
+        use traits::{Median, Quantile};
+
+        let mut histogram = Histogram::new(3);
+        for &(value, weight) in &[(1., 0.5), (2., 1.5), (4., 0.25), (8., 1.75), (3., 0.)] {
+            histogram.insert(value, weight);
+        }
+        assert_eq!(histogram.count(), 4.);
+        assert_eq!(
+            histogram.bins().get(&BinAddress::new(1., 2.)),
+            Some(&BinData::new(2., 3.5, 6.5))
+        );
+        assert_eq!(histogram.median(), Some(2.));
+        assert_eq!(histogram.quantile(0.25), Some(1.5));
+        assert_eq!(histogram.mean(), Some(4.625));
+
+        let bytes = histogram.to_bytes();
+        let restored = Histogram::<f64, f64>::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.bins(), histogram.bins());
+    }
+
+    #[test]
+    fn non_finite() {
+        let mut histogram =
+            Histogram::new(3).with_non_finite_policy(NonFinitePolicy::Clamp { min: 0., max: 100. });
+        for i in &[1., f64::NAN, 2., f64::INFINITY, f64::NEG_INFINITY] {
+            histogram.insert(*i, 1u64);
+        }
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(histogram.max(), Some(100.));
+        assert_eq!(histogram.non_finite().nan(), 1);
+        assert_eq!(
+            histogram.try_insert(f64::NAN, 1),
+            Ok(()),
+            "only the default policy rejects"
+        );
+
+        let mut rejecting = Histogram::new(3);
+        rejecting.insert(f64::NAN, 1u64);
+        rejecting.insert(f64::INFINITY, 2);
+        assert_eq!(rejecting.count(), 0);
+        assert_eq!(rejecting.bins().len(), 0);
+        assert_eq!(
+            rejecting.try_insert(f64::NEG_INFINITY, 1),
+            Err(HistogramError::Infinite)
+        );
+        let restored = Histogram::<f64, u64>::from_bytes(&rejecting.to_bytes()).unwrap();
+        assert_eq!(restored.non_finite(), rejecting.non_finite());
+
+        histogram.merge_ref(&rejecting);
+        assert_eq!(histogram.non_finite().nan(), 3);
+        assert_eq!(histogram.non_finite().pos_inf(), 2);
+        assert_eq!(histogram.count(), 4);
+
+        // the counters are checked for overflow like the total
+        let mut small = Histogram::<f64, u8>::new(3)
+            .with_non_finite_policy(NonFinitePolicy::Clamp { min: 0., max: 1. });
+        assert_eq!(small.try_insert(f64::NAN, 200), Ok(()));
+        assert_eq!(
+            small.try_insert(f64::NAN, 200),
+            Err(HistogramError::Overflow)
+        );
+        assert_eq!(small.try_insert(f64::INFINITY, 200), Ok(()));
+        let mut counting = small
+            .empty_clone()
+            .with_non_finite_policy(NonFinitePolicy::Count);
+        counting.insert(f64::NAN, 100);
+        assert_eq!(small.try_merge(counting), Err(HistogramError::Overflow));
+        assert_eq!(small.non_finite().nan(), 200);
+    }
+
+    #[test]
+    fn binary_encoding() {
+        let mut histogram = Histogram::new(3);
+        for i in &[-1.5, 1., 1., 2., 3.5, 2.1, 3.6] {
+            histogram.insert(*i, 1u64);
+        }
+        let bytes = histogram.to_bytes();
+        let mut restored = Histogram::<f64, u64>::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.bins(), histogram.bins());
+        assert_eq!(restored.count(), 7);
+        assert_eq!(restored.moments(), histogram.moments());
+        restored.insert(10., 1);
+        assert_eq!(restored.bins().len(), 3);
+    }
+
+    #[cfg(feature = "abomonation")]
+    #[test]
+    fn abomonation() {
+        use abomonation::{decode, encode};
+
+        let mut histogram = Histogram::new(3);
+        for i in &[1., 1., 2., 3.5, 2.1, 3.6] {
+            histogram.insert(*i, 1u32);
+        }
+        let serializable = SerializableHistogram::from(histogram.clone());
+        let mut bytes = Vec::new();
+        unsafe { encode(&serializable, &mut bytes).unwrap() };
+        let (decoded, rest) =
+            unsafe { decode::<SerializableHistogram<f64, u32>>(&mut bytes) }.unwrap();
+        assert!(rest.is_empty());
+        let restored: Histogram<f64, u32> = decoded.clone().into();
+        assert_eq!(restored.bins(), histogram.bins());
+        assert_eq!(restored.count(), 6);
+        assert_eq!(restored.moments(), histogram.moments());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        use serde_json;
+
+        let mut histogram = Histogram::new(3);
+        for i in &[1., 1., 2., 3.5, 2.1, 3.6] {
+            histogram.insert(*i, 1u32);
+        }
+        let json = serde_json::to_string(&histogram).unwrap();
+        let mut restored: Histogram<f64, u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.bins(), histogram.bins());
+        assert_eq!(restored.count(), 6);
+        assert_eq!(restored.moments(), histogram.moments());
+        // the merge heap is rebuilt, so the restored histogram keeps merging
+        restored.insert(10., 1);
+        assert_eq!(restored.bins().len(), 3);
+
+        let unsorted = json.replacen("[1.0,", "[5.0,", 1);
+        assert!(serde_json::from_str::<Histogram<f64, u32>>(&unsorted).is_err());
+        let over_cap = json.replace("\"n_bins\":3", "\"n_bins\":2");
+        assert!(serde_json::from_str::<Histogram<f64, u32>>(&over_cap).is_err());
+        let huge_cap = json.replace("\"n_bins\":3", &format!("\"n_bins\":{}", 1u64 << 62));
+        assert!(serde_json::from_str::<Histogram<f64, u32>>(&huge_cap).is_err());
+        let max_cap = json.replace("\"n_bins\":3", &format!("\"n_bins\":{}", ::MAX_BINS));
+        assert!(serde_json::from_str::<Histogram<f64, u32>>(&max_cap).is_ok());
+    }
+
+    #[test]
+    fn unbounded() {
+        let mut histogram = Histogram::new(usize::MAX);
+        for i in 0..100 {
+            histogram.insert((i % 7) as f64, 1u32);
+        }
+        assert_eq!(histogram.bins().len(), 7);
+        let mut empty = histogram.empty_clone();
+        empty.merge_ref(&histogram);
+        assert_eq!(empty.count(), 100);
+    }
+
+    #[test]
+    fn nan_distances() {
+        // NaN distances are merged last instead of breaking the heap order
+        let distance = |distance| BinDistance {
+            left: BinAddress::init(0.),
+            right: BinAddress::init(1.),
+            left_count: 1u32,
+            right_count: 1,
+            distance,
+        };
+        let mut heap = [2., f64::NAN, 1., f64::NAN, 0.5, 3.]
+            .iter()
+            .map(|d| distance(*d))
+            .collect::<BinaryHeap<_>>();
+        let mut order = Vec::new();
+        while let Some(closest) = heap.pop() {
+            order.push(closest.distance);
+        }
+        assert_eq!(order[..4], [0.5, 1., 2., 3.]);
+        assert!(order[4..].iter().all(|d| d.is_nan()));
+        assert_eq!(distance(f64::NAN), distance(f64::NAN));
+    }
+}
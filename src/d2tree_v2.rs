@@ -1,84 +1,618 @@
-use super::traits::DynamicHistogram;
+use super::error::HistogramError;
+use super::non_finite::{NonFinite, NonFinitePolicy};
+use super::sets::HistogramSetItem;
+use super::traits::fallible::{check_count, check_total};
+use super::traits::{
+    BinSummary, DynamicHistogram, EmptyClone, GapDistance, IterBins, Merge, MergeRef,
+    MergeStrategy, Moments, RunningMoments, TryInsert, TryMerge,
+};
 use binary_heap_plus::{BinaryHeap, MinComparator};
+use num::traits::NumAssign;
+use num::{Bounded, ToPrimitive};
 use std::cmp::Ordering;
-use std::ops::AddAssign;
+use std::fmt;
 use std::rc::{Rc, Weak};
 
+/// Histogram keeping its bins in a sorted `Vec` of shared pointers and the
+/// distances between neighbouring bins in a min-heap of weak links.
+///
+/// Bins are never modified in place. Any change replaces the `Rc` of a bin,
+/// which invalidates all distances pointing to the old bin. Stale distances
+/// are skipped when they are popped from the heap, so finding the closest
+/// pair of bins is a heap pop instead of a linear scan.
 #[derive(Clone)]
-pub struct Histogram<V: Ord, C> {
+pub struct Histogram<V, C, S = GapDistance> {
     bins: Vec<Rc<Bin<V, C>>>,
     distances: BinaryHeap<BinDistance<V, C>, MinComparator>,
+    n_bins: usize,
+    total: C,
+    moments: RunningMoments<V>,
+    strategy: S,
+    non_finite: NonFinite<V, C>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Bin<V, C> {
     left: V,
     right: V,
     count: C,
     sum: V,
+    sum_sq: V,
 }
 
 #[derive(Clone)]
 pub struct BinDistance<V, C> {
     left: Weak<Bin<V, C>>,
     right: Weak<Bin<V, C>>,
-    distance: V,
+    distance: f64,
 }
 
-impl<V: PartialEq, C> PartialEq for BinDistance<V, C> {
+impl<V, C> PartialEq for BinDistance<V, C> {
     fn eq(&self, other: &Self) -> bool {
         self.distance.eq(&other.distance)
     }
 }
 
-impl<V: Eq, C> Eq for BinDistance<V, C> {}
+impl<V, C> Eq for BinDistance<V, C> {}
 
-impl<V: PartialOrd, C> PartialOrd for BinDistance<V, C> {
+impl<V, C> PartialOrd for BinDistance<V, C> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.distance.partial_cmp(&other.distance)
+        Some(self.cmp(other))
     }
 }
 
-impl<V: Ord, C> Ord for BinDistance<V, C> {
+impl<V, C> Ord for BinDistance<V, C> {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.distance.cmp(&other.distance)
+        self.distance
+            .partial_cmp(&other.distance)
+            .unwrap_or(Ordering::Equal)
     }
 }
 
-impl<V: Ord + Clone, C: Clone + AddAssign> DynamicHistogram<V, C> for Histogram<V, C> {
-    /// Type of a bin in this histogram
-    type Bin = Bin<V, C>;
+impl<V: PartialOrd + Copy + NumAssign, C: Copy + NumAssign> Bin<V, C> {
+    /// Merges this bin with another one, summing the number of points
+    /// and widening the bin boundaries to contain both bins
+    fn merge(&self, other: &Self) -> Self {
+        Bin {
+            left: if other.left < self.left {
+                other.left
+            } else {
+                self.left
+            },
+            right: if other.right > self.right {
+                other.right
+            } else {
+                self.right
+            },
+            count: self.count + other.count,
+            sum: self.sum + other.sum,
+            sum_sq: self.sum_sq + other.sum_sq,
+        }
+    }
+}
 
-    /// Instantiate a histogram with the given number of maximum bins
-    fn new(n_bins: usize) -> Self {
+impl<V: Copy, C: Copy> Bin<V, C> {
+    pub fn left(&self) -> V {
+        self.left
+    }
+
+    pub fn right(&self) -> V {
+        self.right
+    }
+
+    pub fn count(&self) -> C {
+        self.count
+    }
+
+    pub fn sum(&self) -> V {
+        self.sum
+    }
+
+    /// Sum of the squares of all points in this bin
+    pub fn sum_sq(&self) -> V {
+        self.sum_sq
+    }
+
+    fn summary(&self) -> BinSummary<V, C> {
+        BinSummary {
+            left: self.left,
+            right: self.right,
+            count: self.count,
+            sum: self.sum,
+            sum_sq: self.sum_sq,
+        }
+    }
+}
+
+impl<V: PartialOrd + Copy + NumAssign, C: Copy + NumAssign> BinDistance<V, C> {
+    fn new<S: MergeStrategy<V, C>>(
+        strategy: &S,
+        left: &Rc<Bin<V, C>>,
+        right: &Rc<Bin<V, C>>,
+    ) -> Self {
+        BinDistance {
+            left: Rc::downgrade(left),
+            right: Rc::downgrade(right),
+            distance: strategy.cost(&left.summary(), &right.summary()),
+        }
+    }
+}
+
+impl<V, C> Histogram<V, C>
+where
+    V: PartialOrd + Copy + NumAssign + ToPrimitive,
+    C: Copy + NumAssign + Into<V> + ToPrimitive,
+{
+    /// Instantiate a histogram with the given number of maximum bins,
+    /// merging the bins with the smallest gap first
+    pub fn new(n_bins: usize) -> Self {
+        Self::with_strategy(n_bins, GapDistance)
+    }
+}
+
+impl<V: PartialOrd + Copy + NumAssign, C: Copy + NumAssign, S: MergeStrategy<V, C>>
+    Histogram<V, C, S>
+{
+    /// Instantiate a histogram with the given number of maximum bins,
+    /// which uses `strategy` to decide which bins to merge
+    pub fn with_strategy(n_bins: usize, strategy: S) -> Self {
         Histogram {
-            bins: vec![],
+            bins: Vec::new(),
             distances: BinaryHeap::new_min(),
+            n_bins,
+            total: C::zero(),
+            moments: RunningMoments::default(),
+            strategy,
+            non_finite: NonFinite::default(),
         }
     }
 
-    /// Insert a new data point into this histogram
-    fn insert(&mut self, value: V, count: C) {
-        let search_result = self.bins.binary_search_by(|probe| {
-            if probe.left <= value && probe.right > value {
+    /// Use `policy` for NaN and infinite values instead of rejecting them.
+    /// Panics if the bounds of a `Clamp` policy are not finite or `min` is
+    /// greater than `max`.
+    pub fn with_non_finite_policy(mut self, policy: NonFinitePolicy<V>) -> Self
+    where
+        V: ToPrimitive,
+    {
+        self.non_finite = NonFinite::new(policy);
+        self
+    }
+
+    /// Policy for NaN and infinite values, with the counts of those that
+    /// were left out of the bins and so of `count`
+    pub fn non_finite(&self) -> &NonFinite<V, C> {
+        &self.non_finite
+    }
+
+    fn search_bins(&self, value: V) -> Result<usize, usize> {
+        self.bins.binary_search_by(|probe| {
+            if probe.left <= value && probe.right >= value {
                 Ordering::Equal
             } else if probe.left > value {
                 Ordering::Greater
             } else {
                 Ordering::Less
             }
-        });
+        })
+    }
+
+    /// Find the index of a bin that is still part of this histogram
+    fn position(&self, bin: &Rc<Bin<V, C>>) -> Option<usize> {
+        let index = self.search_bins(bin.left).ok()?;
+        if Rc::ptr_eq(&self.bins[index], bin) {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Push the distances between the bin at `index` and its neighbours
+    fn push_distances(&mut self, index: usize) {
+        if index > 0 {
+            let dist = BinDistance::new(&self.strategy, &self.bins[index - 1], &self.bins[index]);
+            self.distances.push(dist);
+        }
+        if index + 1 < self.bins.len() {
+            let dist = BinDistance::new(&self.strategy, &self.bins[index], &self.bins[index + 1]);
+            self.distances.push(dist);
+        }
+    }
+
+    fn rebuild_distances(&mut self) {
+        self.distances.clear();
+        for (left, right) in self.bins.iter().zip(self.bins.iter().skip(1)) {
+            self.distances
+                .push(BinDistance::new(&self.strategy, left, right));
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        while self.bins.len() > self.n_bins {
+            let closest = match self.distances.pop() {
+                Some(closest) => closest,
+                None => {
+                    self.rebuild_distances();
+                    if self.distances.is_empty() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            // distances are stale if either bin was replaced in the meantime
+            // or a new bin was inserted between them. Bins may also be kept
+            // alive by a clone of this histogram, so check they are still ours
+            let (left, right) = match (closest.left.upgrade(), closest.right.upgrade()) {
+                (Some(left), Some(right)) => (left, right),
+                _ => continue,
+            };
+            let index = match self.position(&left) {
+                Some(index)
+                    if self
+                        .bins
+                        .get(index + 1)
+                        .is_some_and(|next| Rc::ptr_eq(next, &right)) =>
+                {
+                    index
+                }
+                _ => continue,
+            };
+
+            self.bins[index] = Rc::new(left.merge(&right));
+            self.bins.remove(index + 1);
+            self.push_distances(index);
+        }
+
+        // drop the stale distances piling up from inserts and merges
+        if self.distances.len() > self.n_bins.saturating_mul(10) {
+            self.rebuild_distances();
+        }
+    }
+
+    /// Replace all bins with the given ones, merging any overlapping bins
+    /// to keep the bins of this histogram disjoint
+    fn rebuild_bins(&mut self, mut bins: Vec<Rc<Bin<V, C>>>) {
+        bins.sort_by(|a, b| a.left.partial_cmp(&b.left).unwrap_or(Ordering::Equal));
+        self.bins.clear();
+        for bin in bins {
+            let overlaps = match self.bins.last() {
+                Some(last) => bin.left <= last.right,
+                None => false,
+            };
+            if overlaps {
+                let last = self.bins.pop().unwrap();
+                self.bins.push(Rc::new(last.merge(&bin)));
+            } else {
+                self.bins.push(bin);
+            }
+        }
+        self.rebuild_distances();
+        self.shrink_to_fit();
+    }
+
+    /// Iterate over the bins of this histogram in ascending order
+    pub fn bins(&self) -> impl Iterator<Item = &Bin<V, C>> {
+        self.bins.iter().map(|bin| bin.as_ref())
+    }
+}
+
+impl<V, C, S> DynamicHistogram<V, C> for Histogram<V, C, S>
+where
+    V: PartialOrd + Copy + NumAssign + ToPrimitive,
+    C: Copy + NumAssign + Into<V> + ToPrimitive,
+    S: MergeStrategy<V, C> + Default,
+{
+    /// Type of a bin in this histogram
+    type Bin = Bin<V, C>;
 
-        match search_result {
+    /// Instantiate a histogram with the given number of maximum bins
+    fn new(n_bins: usize) -> Self {
+        Self::with_strategy(n_bins, S::default())
+    }
+
+    /// Insert a new data point into this histogram
+    fn insert(&mut self, value: V, count: C) {
+        let weight = match count.to_f64() {
+            Some(weight) if weight > 0. => weight,
+            _ => return,
+        };
+        let value = match self.non_finite.admit(value, count) {
+            Some(value) => value,
+            None => return,
+        };
+        self.total += count;
+        self.moments.insert(value, weight);
+        let (index, created) = match self.search_bins(value) {
             Ok(found) => {
-                unimplemented!();
+                let mut bin = Bin::clone(&self.bins[found]);
+                bin.count += count;
+                bin.sum += value * count.into();
+                bin.sum_sq += value * value * count.into();
+                self.bins[found] = Rc::new(bin);
+                (found, false)
+            }
+            Err(insert_at) => {
+                let bin = Bin {
+                    left: value,
+                    right: value,
+                    count,
+                    sum: value * count.into(),
+                    sum_sq: value * value * count.into(),
+                };
+                self.bins.insert(insert_at, Rc::new(bin));
+                (insert_at, true)
             }
-            Err(insert_at) => unimplemented!(),
+        };
+        // distances are only needed once the histogram is full, so they are
+        // built when it fills up and kept up to date from then on
+        match self.bins.len().cmp(&self.n_bins) {
+            Ordering::Less => {}
+            Ordering::Equal if created => self.rebuild_distances(),
+            _ => self.push_distances(index),
         }
+        self.shrink_to_fit();
     }
 
     /// Count the total number of data points in this histogram (over all bins)
     fn count(&self) -> C {
-        unimplemented!()
+        self.total
+    }
+}
+
+impl<V, C, S> EmptyClone for Histogram<V, C, S>
+where
+    V: PartialOrd + Copy + NumAssign,
+    C: Copy + NumAssign,
+    S: MergeStrategy<V, C> + Clone,
+{
+    fn empty_clone(&self) -> Self {
+        let mut histogram = Histogram::with_strategy(self.n_bins, self.strategy.clone());
+        histogram.non_finite = self.non_finite.empty_clone();
+        histogram
+    }
+}
+
+impl<V, C, S> MergeRef for Histogram<V, C, S>
+where
+    V: PartialOrd + Copy + NumAssign + ToPrimitive,
+    C: Copy + NumAssign,
+    S: MergeStrategy<V, C>,
+{
+    fn merge_ref(&mut self, other: &Self) {
+        self.total += other.total;
+        self.moments.merge(&other.moments);
+        self.non_finite.merge(&other.non_finite);
+        let mut bins = self.bins.split_off(0);
+        bins.extend(other.bins.iter().cloned());
+        self.rebuild_bins(bins);
+    }
+}
+
+impl<V, C, S> Merge for Histogram<V, C, S>
+where
+    V: PartialOrd + Copy + NumAssign + ToPrimitive,
+    C: Copy + NumAssign,
+    S: MergeStrategy<V, C>,
+{
+    fn merge(&mut self, other: Self) {
+        self.total += other.total;
+        self.moments.merge(&other.moments);
+        self.non_finite.merge(&other.non_finite);
+        let mut bins = self.bins.split_off(0);
+        bins.extend(other.bins);
+        self.rebuild_bins(bins);
+    }
+}
+
+impl<V, C, S> TryInsert<V, C> for Histogram<V, C, S>
+where
+    V: PartialOrd + Copy + NumAssign + ToPrimitive,
+    C: Copy + PartialOrd + Bounded + NumAssign + Into<V> + ToPrimitive,
+    S: MergeStrategy<V, C> + Default,
+{
+    fn try_insert(&mut self, value: V, count: C) -> Result<(), HistogramError> {
+        check_count(count, self.total, self.n_bins)?;
+        self.non_finite.check(value, count)?;
+        self.insert(value, count);
+        Ok(())
+    }
+}
+
+impl<V, C, S> TryMerge for Histogram<V, C, S>
+where
+    V: PartialOrd + Copy + NumAssign + ToPrimitive,
+    C: Copy + PartialOrd + Bounded + NumAssign,
+    S: MergeStrategy<V, C>,
+{
+    fn try_merge(&mut self, other: Self) -> Result<(), HistogramError> {
+        if self.n_bins == 0 {
+            return Err(HistogramError::ZeroCapacity);
+        }
+        check_total(self.total, other.total)?;
+        self.non_finite.check_merge(&other.non_finite)?;
+        self.merge(other);
+        Ok(())
+    }
+}
+
+impl<V, C, S> HistogramSetItem for Histogram<V, C, S>
+where
+    V: PartialOrd + Copy + NumAssign + ToPrimitive,
+    C: Copy + NumAssign,
+    S: MergeStrategy<V, C> + Clone,
+{
+    type Serializable = Self;
+}
+
+impl<V: Copy, C: Copy, S> IterBins for Histogram<V, C, S> {
+    type Value = V;
+    type Count = C;
+
+    fn iter_bins(&self) -> impl Iterator<Item = BinSummary<V, C>> + '_ {
+        self.bins.iter().map(|bin| bin.summary())
+    }
+}
+
+impl<V: Copy, C, S> Moments<V> for Histogram<V, C, S> {
+    fn moments(&self) -> &RunningMoments<V> {
+        &self.moments
+    }
+}
+
+impl<V: fmt::Debug, C: fmt::Debug, S: fmt::Debug> fmt::Debug for Histogram<V, C, S> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Histogram")
+            .field("n_bins", &self.n_bins)
+            .field("total", &self.total)
+            .field("strategy", &self.strategy)
+            .field("non_finite", &self.non_finite)
+            .field("bins", &self.bins)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bin(left: f64, right: f64, count: u32, sum: f64, sum_sq: f64) -> Bin<f64, u32> {
+        Bin {
+            left,
+            right,
+            count,
+            sum,
+            sum_sq,
+        }
+    }
+
+    #[test]
+    fn insert() {
+        let mut h = Histogram::new(3);
+        let samples: &[(f64, u32)] = &[(1., 1), (1., 1), (2., 1), (3.5, 1), (2.1, 1), (3.6, 1)];
+        h.insert_iter(samples);
+
+        assert_eq!(
+            h.bins().cloned().collect::<Vec<_>>(),
+            vec![
+                bin(1., 1., 2, 2., 2.),
+                bin(2., 2.1, 2, 4.1, 2. * 2. + 2.1 * 2.1),
+                bin(3.5, 3.6, 2, 7.1, 3.5 * 3.5 + 3.6 * 3.6)
+            ]
+        );
+        assert_eq!(h.count(), 6);
+    }
+
+    #[test]
+    fn insert_many() {
+        let mut h = Histogram::new(50);
+        for i in 0..10_000u32 {
+            h.insert(f64::from((i * 7919) % 10_000), 1u32);
+        }
+        assert_eq!(h.bins().count(), 50);
+        assert_eq!(h.count(), 10_000);
+        let bins = h.bins().collect::<Vec<_>>();
+        for (bin, next) in bins.iter().zip(bins.iter().skip(1)) {
+            assert!(bin.right < next.left);
+        }
+    }
+
+    #[test]
+    fn bounded_distances() {
+        let mut h = Histogram::new(100);
+        for i in 0..10_000u32 {
+            h.insert(f64::from(i % 5), 1u32);
+        }
+        assert_eq!(h.bins().count(), 5);
+        assert!(h.distances.len() <= 1000);
+
+        let mut full = Histogram::new(10);
+        for i in 0..10_000u32 {
+            h.insert(f64::from(i % 5), 1u32);
+            full.insert(f64::from(i % 50), 1u32);
+        }
+        assert_eq!(full.bins().count(), 10);
+        assert!(h.distances.len() <= 1000);
+        assert!(full.distances.len() <= 100);
+    }
+
+    #[test]
+    fn merge() {
+        let mut h1 = Histogram::new(3);
+        for i in &[1., 1.5, 3., 4., 4.5, 6.] {
+            h1.insert(*i, 1u32);
+        }
+
+        let mut h2 = Histogram::new(3);
+        for i in &[1., 7., 5.] {
+            h2.insert(*i, 1u32);
+        }
+        h1.merge_ref(&h2);
+        assert_eq!(h1.count(), 9);
+
+        assert_eq!(
+            h1.bins().cloned().collect::<Vec<_>>(),
+            vec![
+                bin(1., 1.5, 3, 3.5, 4.25),
+                bin(3., 5., 4, 16.5, 70.25),
+                bin(6., 7., 2, 13., 85.)
+            ]
+        );
+    }
+
+    #[test]
+    fn non_finite() {
+        let mut h = Histogram::new(3);
+        for i in &[1., f64::NAN, 2., f64::INFINITY] {
+            h.insert(*i, 1u8);
+        }
+        assert_eq!(h.count(), 2);
+        assert_eq!(h.bins().count(), 2);
+        assert_eq!(h.max(), Some(2.));
+        assert_eq!(h.non_finite().nan(), 1);
+        assert_eq!(h.non_finite().pos_inf(), 1);
+        // rejected by default
+        assert_eq!(
+            h.try_insert(f64::NEG_INFINITY, 1),
+            Err(HistogramError::Infinite)
+        );
+        assert_eq!(h.try_insert(f64::NAN, 1), Err(HistogramError::NotANumber));
+
+        let mut clamped = h
+            .empty_clone()
+            .with_non_finite_policy(NonFinitePolicy::Clamp { min: 0., max: 10. });
+        assert_eq!(clamped.try_insert(f64::INFINITY, 1), Ok(()));
+        assert_eq!(clamped.try_insert(f64::NAN, 200), Ok(()));
+        assert_eq!(
+            clamped.try_insert(f64::NAN, 100),
+            Err(HistogramError::Overflow)
+        );
+        assert_eq!(clamped.max(), Some(10.));
+        let mut twice = clamped.clone();
+        assert_eq!(
+            twice.try_merge(clamped.clone()),
+            Err(HistogramError::Overflow)
+        );
+
+        let mut counting = clamped
+            .empty_clone()
+            .with_non_finite_policy(NonFinitePolicy::Count);
+        counting.insert(f64::INFINITY, 2);
+        counting.merge_ref(&clamped);
+        assert_eq!(counting.count(), 1);
+        assert_eq!(counting.non_finite().nan(), 200);
+        assert_eq!(counting.non_finite().pos_inf(), 2);
+        assert_eq!(counting.non_finite().policy(), &NonFinitePolicy::Count);
+    }
+
+    #[test]
+    fn unbounded() {
+        let mut h = Histogram::new(usize::MAX);
+        for i in 0..100 {
+            h.insert(f64::from(i % 7), 1u32);
+        }
+        assert_eq!(h.bins().count(), 7);
+        let mut empty = h.empty_clone();
+        empty.merge_ref(&h);
+        assert_eq!(empty.count(), 100);
     }
 }
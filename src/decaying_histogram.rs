@@ -0,0 +1,226 @@
+use simple_vec_histogram::SimpleVecHistogram;
+use traits::{
+    BinSummary, DynamicHistogram, EmptyClone, GapDistance, IterBins, Merge, MergeRef,
+    MergeStrategy, Moments, RunningMoments,
+};
+
+/// Number of half-lives the time may advance past the landmark before the
+/// stored weights are renormalised, keeping them far from overflowing
+const RENORMALISE_HALF_LIVES: f64 = 64.;
+
+/// Histogram in which the weight of every point halves each `half_life`,
+/// so that queries describe the recent distribution.
+///
+/// Instead of decaying all bins whenever time advances, points are
+/// inserted with a weight that grows exponentially with their time since
+/// a landmark, and queries divide by the growth at the current time.
+/// Once the growth gets too large, all bins are scaled down once and the
+/// landmark moves forward.
+///
+/// Times are plain numbers in the same unit as the half-life, e.g.
+/// seconds. Queries describe the histogram at the latest time passed to
+/// [`insert`](#method.insert) or [`advance`](#method.advance).
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecayingHistogram<S = GapDistance> {
+    histogram: SimpleVecHistogram<f64, f64, S>,
+    half_life: f64,
+    /// Time at which the stored weights equal the decayed weights
+    landmark: f64,
+    now: f64,
+}
+
+impl DecayingHistogram {
+    /// Instantiate a histogram with the given number of maximum bins and
+    /// half-life, merging the bins with the smallest gap first
+    pub fn new(n_bins: usize, half_life: f64) -> Self {
+        Self::with_strategy(n_bins, half_life, GapDistance)
+    }
+}
+
+impl<S> DecayingHistogram<S> {
+    /// Instantiate a histogram with the given number of maximum bins and
+    /// half-life, which uses `strategy` to decide which bins to merge.
+    ///
+    /// Panics if the half-life is not positive.
+    pub fn with_strategy(n_bins: usize, half_life: f64, strategy: S) -> Self {
+        assert!(half_life > 0., "half-life must be positive");
+        DecayingHistogram {
+            histogram: SimpleVecHistogram::with_strategy(n_bins, strategy),
+            half_life,
+            landmark: 0.,
+            now: 0.,
+        }
+    }
+
+    /// Time after which the weight of a point has halved
+    pub fn half_life(&self) -> f64 {
+        self.half_life
+    }
+
+    /// Time at which queries are answered
+    pub fn now(&self) -> f64 {
+        self.now
+    }
+
+    /// Move the time at which queries are answered forward to `time`.
+    /// Earlier times are ignored.
+    pub fn advance(&mut self, time: f64) {
+        if time > self.now {
+            self.now = time;
+        }
+    }
+
+    /// Factor turning stored weights into weights decayed until `time`
+    fn decay(&self, time: f64) -> f64 {
+        (-(time - self.landmark) / self.half_life).exp2()
+    }
+
+    /// Scale the stored weights to be relative to a new landmark
+    fn renormalise(&mut self, landmark: f64) {
+        if landmark != self.landmark {
+            let factor = self.decay(landmark);
+            self.histogram.scale(factor);
+            self.landmark = landmark;
+        }
+    }
+}
+
+impl<S: MergeStrategy<f64, f64> + Default> DecayingHistogram<S> {
+    /// Insert a point observed at `time` with the given weight, advancing
+    /// the time of this histogram if `time` is later.
+    ///
+    /// Points with a weight that is not positive are ignored, as are
+    /// points so old that their weight has decayed to zero.
+    pub fn insert(&mut self, value: f64, weight: f64, time: f64) {
+        self.advance(time);
+        if (time - self.landmark) / self.half_life > RENORMALISE_HALF_LIVES {
+            self.renormalise(time);
+        }
+        self.histogram.insert(value, weight / self.decay(time));
+    }
+
+    /// Total decayed weight of all points at the current time
+    pub fn count(&self) -> f64 {
+        self.histogram.count() * self.decay(self.now)
+    }
+}
+
+impl<S> IterBins for DecayingHistogram<S> {
+    type Value = f64;
+    type Count = f64;
+
+    /// Bins with their counts and sums decayed to the current time
+    fn iter_bins(&self) -> impl Iterator<Item = BinSummary<f64, f64>> + '_ {
+        let decay = self.decay(self.now);
+        self.histogram.iter_bins().map(move |bin| BinSummary {
+            count: bin.count * decay,
+            sum: bin.sum * decay,
+            sum_sq: bin.sum_sq * decay,
+            ..bin
+        })
+    }
+}
+
+/// The mean and higher moments are weighted by the decayed weights, while
+/// the extremes cover all points ever inserted.
+impl<S> Moments<f64> for DecayingHistogram<S> {
+    fn moments(&self) -> &RunningMoments<f64> {
+        self.histogram.moments()
+    }
+}
+
+impl<S: Clone> EmptyClone for DecayingHistogram<S> {
+    fn empty_clone(&self) -> Self {
+        DecayingHistogram {
+            histogram: self.histogram.empty_clone(),
+            half_life: self.half_life,
+            landmark: self.landmark,
+            now: self.now,
+        }
+    }
+}
+
+/// Both histograms are brought to the later of their landmarks before
+/// merging, and the merged histogram answers queries at the later of their
+/// current times. Both are expected to have the same half-life.
+impl<S: MergeStrategy<f64, f64>> Merge for DecayingHistogram<S> {
+    fn merge(&mut self, mut other: Self) {
+        let landmark = self.landmark.max(other.landmark);
+        self.renormalise(landmark);
+        other.renormalise(landmark);
+        self.advance(other.now);
+        self.histogram.merge(other.histogram);
+    }
+}
+
+impl<S: MergeStrategy<f64, f64> + Clone> MergeRef for DecayingHistogram<S> {
+    fn merge_ref(&mut self, other: &Self) {
+        self.merge(other.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use traits::{Median, MergeIter, Quantile};
+
+    #[test]
+    fn decay() {
+        let mut h = DecayingHistogram::new(8, 10.);
+        h.insert(1., 1., 0.);
+        h.insert(2., 1., 10.);
+        assert_eq!(h.count(), 1.5);
+        assert_eq!(h.cdf(1.), Some(1. / 3.));
+
+        h.advance(20.);
+        assert_eq!(h.count(), 0.75);
+        assert_eq!(h.quantile(0.), Some(1.));
+        assert_eq!(h.median(), Some(2.));
+        assert!((h.mean().unwrap() - 5. / 3.).abs() < 1e-12);
+
+        // going back in time does not undo the decay
+        h.advance(0.);
+        assert_eq!(h.now(), 20.);
+        h.insert(3., 1., -1000.);
+        assert_eq!(h.count(), 0.75);
+    }
+
+    #[test]
+    fn renormalise() {
+        let mut h = DecayingHistogram::new(8, 1.);
+        h.insert(1., 1., 0.);
+        h.insert(2., 1., 100.);
+        assert_eq!(h.landmark, 100.);
+        assert_eq!(h.count(), 1. + (-100f64).exp2());
+        assert_eq!(h.histogram.bins()[0].count(), (-100f64).exp2());
+
+        // the first point decays to nothing
+        h.insert(3., 1., 2000.);
+        assert_eq!(h.count(), 1.);
+        assert_eq!(h.quantile(0.), Some(3.));
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = DecayingHistogram::new(8, 10.);
+        a.insert(1., 1., 690.);
+        let mut b = DecayingHistogram::new(8, 10.);
+        b.insert(2., 1., 700.);
+        assert_eq!((a.landmark, b.landmark), (690., 700.));
+
+        let mut merged = a.clone();
+        merged.merge_ref(&b);
+        assert_eq!(merged.now(), 700.);
+        assert_eq!(merged.count(), 1.5);
+        assert_eq!(merged.median(), Some(2.));
+        assert_eq!(merged.cdf(1.), Some(1. / 3.));
+
+        b.merge(a.clone());
+        assert_eq!(b.count(), 1.5);
+        assert_eq!(b.cdf(1.), Some(1. / 3.));
+        assert_eq!(
+            [a.empty_clone(), a, b].iter().merge().map(|h| h.count()),
+            Some(2.)
+        );
+    }
+}
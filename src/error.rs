@@ -0,0 +1,48 @@
+use std::error::Error;
+use std::fmt;
+
+/// Error returned by the fallible histogram operations, such as
+/// [`TryInsert`](traits/trait.TryInsert.html), instead of panicking or
+/// silently ignoring the input
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistogramError {
+    /// A value or count is NaN
+    NotANumber,
+    /// A value is infinite and the histogram rejects infinite values
+    Infinite,
+    /// A count is zero or negative
+    NonPositiveCount,
+    /// The total count would exceed the largest number of the count type
+    Overflow,
+    /// The histogram has a maximum of zero bins
+    ZeroCapacity,
+    /// A number can't be represented in the type it has to be converted to
+    CastFailed,
+    /// The histogram contains no data points
+    Empty,
+    /// A partial sum was requested for more points than a bin contains
+    RankOutOfRange,
+    /// A histogram set has no template to create a histogram for a new key
+    NoTemplate,
+}
+
+impl fmt::Display for HistogramError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let message = match *self {
+            HistogramError::NotANumber => "value or count is NaN",
+            HistogramError::Infinite => "value is infinite",
+            HistogramError::NonPositiveCount => "count is not positive",
+            HistogramError::Overflow => "total count overflows the count type",
+            HistogramError::ZeroCapacity => "histogram has a maximum of zero bins",
+            HistogramError::CastFailed => "number can't be converted to the required type",
+            HistogramError::Empty => "histogram is empty",
+            HistogramError::RankOutOfRange => "partial sum over more points than the bin contains",
+            HistogramError::NoTemplate => {
+                "histogram set has no template to create a histogram from"
+            }
+        };
+        fmt.write_str(message)
+    }
+}
+
+impl Error for HistogramError {}
@@ -0,0 +1,643 @@
+use num::traits::NumAssign;
+use num::ToPrimitive;
+use ord_subset::{OrdSubset, OrdSubsetSliceExt};
+use simple_vec_histogram::{Bin as LinearBin, MergeCandidate, SimpleVecHistogram};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use traits::{BinSummary, EmptyClone, GapDistance, Merge, MergeRef, RunningMoments};
+
+/// Histogram over pairs of values, keeping a bounded number of
+/// rectangular bins.
+///
+/// When there are too many bins, the two bins with the closest centroids
+/// are merged into the bounding rectangle of both. Distances along each
+/// axis are relative to the range of the values inserted on that axis, so
+/// axes of different units (e.g. latency and payload size) weigh equally.
+///
+/// Once the histogram is full, the distances between all pairs of bins are
+/// kept in a heap. Distances become stale when a bin changes or the range
+/// of an axis grows or shrinks by more than a factor of two, and are
+/// skipped or rebuilt instead of rescanning all pairs for every merge.
+#[derive(Clone, Debug)]
+pub struct Histogram2d<V, C> {
+    bins: Vec<Bin<V, C>>,
+    bins_cap: usize,
+    total: C,
+    moments_x: RunningMoments<V>,
+    moments_y: RunningMoments<V>,
+    /// Total count of the points with a NaN or infinite coordinate
+    non_finite: C,
+    distances: BinaryHeap<MergeCandidate>,
+    /// Generation of each bin, bumped whenever the bin at an index changes
+    generations: Vec<usize>,
+    next_generation: usize,
+    /// Scales of both axes the distances were calculated with, which may
+    /// lag behind the ranges of the axes by up to `RESCALE_FACTOR`
+    scales: (f64, f64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bin<V, C> {
+    x: Extent<V>,
+    y: Extent<V>,
+    count: C,
+}
+
+/// Boundaries, sum and sum of squares of the points of a bin along one axis
+#[derive(Clone, Debug, PartialEq)]
+struct Extent<V> {
+    left: V,
+    right: V,
+    sum: V,
+    sum_sq: V,
+}
+
+impl<V: PartialOrd + Copy + NumAssign> Extent<V> {
+    fn new(value: V, weight: V) -> Self {
+        Extent {
+            left: value,
+            right: value,
+            sum: value * weight,
+            sum_sq: value * value * weight,
+        }
+    }
+
+    fn contains(&self, value: V) -> bool {
+        self.left <= value && value <= self.right
+    }
+
+    fn insert(&mut self, value: V, weight: V) {
+        self.sum += value * weight;
+        self.sum_sq += value * value * weight;
+    }
+
+    fn merge(&mut self, other: &Self) {
+        if other.left < self.left {
+            self.left = other.left;
+        }
+        if other.right > self.right {
+            self.right = other.right;
+        }
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+    }
+}
+
+impl<V: Copy, C: Copy> Bin<V, C> {
+    /// Projection of this bin onto the x axis
+    pub fn x(&self) -> BinSummary<V, C> {
+        project(&self.x, self.count)
+    }
+
+    /// Projection of this bin onto the y axis
+    pub fn y(&self) -> BinSummary<V, C> {
+        project(&self.y, self.count)
+    }
+
+    pub fn count(&self) -> C {
+        self.count
+    }
+}
+
+impl<V, C> Bin<V, C>
+where
+    V: PartialOrd + Copy + NumAssign + ToPrimitive,
+    C: Copy + NumAssign + ToPrimitive,
+{
+    fn merge(&mut self, other: &Self) {
+        self.x.merge(&other.x);
+        self.y.merge(&other.y);
+        self.count += other.count;
+    }
+
+    /// Distance between the centroids of two bins, with both axes
+    /// divided by the given scales
+    fn distance(&self, other: &Self, (scale_x, scale_y): (f64, f64)) -> f64 {
+        let dx = (centroid(&self.x, self.count) - centroid(&other.x, other.count)) / scale_x;
+        let dy = (centroid(&self.y, self.count) - centroid(&other.y, other.count)) / scale_y;
+        (dx * dx + dy * dy).sqrt()
+    }
+}
+
+fn project<V: Copy, C>(extent: &Extent<V>, count: C) -> BinSummary<V, C> {
+    BinSummary {
+        left: extent.left,
+        right: extent.right,
+        count,
+        sum: extent.sum,
+        sum_sq: extent.sum_sq,
+    }
+}
+
+fn centroid<V: ToPrimitive, C: ToPrimitive>(extent: &Extent<V>, count: C) -> f64 {
+    let sum = extent.sum.to_f64().unwrap_or(f64::NAN);
+    sum / count.to_f64().unwrap_or(f64::NAN)
+}
+
+/// Factor by which the range of an axis may grow or shrink before the
+/// distances between bins are calculated again with the new range
+const RESCALE_FACTOR: f64 = 2.;
+
+/// Whether the distances calculated with `old` as the scale of an axis are
+/// too far off for the scale `new`
+fn rescaled(old: f64, new: f64) -> bool {
+    new > old * RESCALE_FACTOR || new * RESCALE_FACTOR < old
+}
+
+/// Range of all values on an axis, or 1 if there is none yet
+fn scale<V: Copy + ToPrimitive>(moments: &RunningMoments<V>) -> f64 {
+    let range = match (moments.min(), moments.max()) {
+        (Some(min), Some(max)) => max.to_f64().unwrap_or(f64::NAN) - min.to_f64().unwrap_or(0.),
+        _ => 0.,
+    };
+    if range > 0. {
+        range
+    } else {
+        1.
+    }
+}
+
+impl<V: PartialEq, C: PartialEq> PartialEq for Histogram2d<V, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bins == other.bins
+            && self.bins_cap == other.bins_cap
+            && self.total == other.total
+            && self.moments_x == other.moments_x
+            && self.moments_y == other.moments_y
+            && self.non_finite == other.non_finite
+    }
+}
+
+impl<V, C> Histogram2d<V, C>
+where
+    V: PartialOrd + OrdSubset + Copy + NumAssign + ToPrimitive,
+    C: Copy + NumAssign + Into<V> + ToPrimitive,
+{
+    /// Instantiate a histogram with the given number of maximum bins
+    pub fn new(n_bins: usize) -> Self {
+        Histogram2d {
+            bins: Vec::new(),
+            bins_cap: n_bins,
+            total: C::zero(),
+            moments_x: RunningMoments::default(),
+            moments_y: RunningMoments::default(),
+            non_finite: C::zero(),
+            distances: BinaryHeap::new(),
+            generations: Vec::new(),
+            next_generation: 0,
+            scales: (1., 1.),
+        }
+    }
+
+    /// Insert a new data point with the given weight into this histogram.
+    /// Points with a weight that is not positive are ignored. Points with a
+    /// NaN or infinite coordinate have no place among the bins and are only
+    /// counted in `non_finite`.
+    pub fn insert(&mut self, x: V, y: V, count: C) {
+        let weight = match count.to_f64() {
+            Some(weight) if weight > 0. => weight,
+            _ => return,
+        };
+        let is_finite = |value: V| value.to_f64().is_some_and(f64::is_finite);
+        if !is_finite(x) || !is_finite(y) {
+            self.non_finite += count;
+            return;
+        }
+        self.total += count;
+        self.moments_x.insert(x, weight);
+        self.moments_y.insert(y, weight);
+
+        let value_count = count.into();
+        let existing = self
+            .bins
+            .iter()
+            .position(|bin| bin.x.contains(x) && bin.y.contains(y));
+        let (index, created) = match existing {
+            Some(index) => {
+                let bin = &mut self.bins[index];
+                bin.x.insert(x, value_count);
+                bin.y.insert(y, value_count);
+                bin.count += count;
+                (index, false)
+            }
+            None => {
+                self.bins.push(Bin {
+                    x: Extent::new(x, value_count),
+                    y: Extent::new(y, value_count),
+                    count,
+                });
+                self.generations.push(0);
+                (self.bins.len() - 1, true)
+            }
+        };
+        self.touch(index);
+
+        // distances are only needed once the histogram is full, so they are
+        // built when it fills up and kept up to date from then on. They keep
+        // the scales they were built with until an axis range has changed
+        // by more than `RESCALE_FACTOR`, so that growing ranges, e.g. of
+        // timestamps, don't rebuild them on every insert
+        let rescaled = rescaled(self.scales.0, scale(&self.moments_x))
+            || rescaled(self.scales.1, scale(&self.moments_y));
+        match self.bins.len().cmp(&self.bins_cap) {
+            Ordering::Less => {}
+            Ordering::Equal if created => self.rebuild_distances(),
+            _ if rescaled => self.rebuild_distances(),
+            _ => self.push_distances(index),
+        }
+        self.shrink_to_fit();
+    }
+
+    /// Insert all data points of an iterator into this histogram
+    pub fn insert_iter<'a>(&mut self, points: impl IntoIterator<Item = &'a (V, V, C)>)
+    where
+        V: 'a,
+        C: 'a,
+    {
+        for (x, y, count) in points {
+            self.insert(*x, *y, *count);
+        }
+    }
+
+    /// Count the total number of data points in this histogram (over all bins)
+    pub fn count(&self) -> C {
+        self.total
+    }
+
+    /// Total count of the points that were left out of the bins and so of
+    /// `count` because a coordinate was NaN or infinite
+    pub fn non_finite(&self) -> C {
+        self.non_finite
+    }
+
+    /// The bins of this histogram, in no particular order
+    pub fn bins(&self) -> &[Bin<V, C>] {
+        self.bins.as_slice()
+    }
+
+    /// Exact moments of the x values of all points
+    pub fn moments_x(&self) -> &RunningMoments<V> {
+        &self.moments_x
+    }
+
+    /// Exact moments of the y values of all points
+    pub fn moments_y(&self) -> &RunningMoments<V> {
+        &self.moments_y
+    }
+
+    /// Histogram of the x values of all points, with one bin per bin of
+    /// this histogram. Bins whose projections overlap share a bin.
+    pub fn marginal_x(&self) -> SimpleVecHistogram<V, C> {
+        self.marginal(Bin::x, &self.moments_x)
+    }
+
+    /// Histogram of the y values of all points, with one bin per bin of
+    /// this histogram. Bins whose projections overlap share a bin.
+    pub fn marginal_y(&self) -> SimpleVecHistogram<V, C> {
+        self.marginal(Bin::y, &self.moments_y)
+    }
+
+    fn marginal(
+        &self,
+        project: fn(&Bin<V, C>) -> BinSummary<V, C>,
+        moments: &RunningMoments<V>,
+    ) -> SimpleVecHistogram<V, C> {
+        let mut projections = self
+            .bins
+            .iter()
+            .map(|bin| LinearBin::from(project(bin)))
+            .collect::<Vec<_>>();
+        projections.ord_subset_sort_by_key(|bin| bin.left());
+
+        // projections of different rectangles may overlap, but the bins of a
+        // linear histogram have to be disjoint
+        let mut bins: Vec<LinearBin<V, C>> = Vec::with_capacity(projections.len());
+        for bin in projections {
+            match bins.last_mut() {
+                Some(last) if bin.left() < last.right() || bin.left() == last.left() => {
+                    last.absorb(bin)
+                }
+                _ => bins.push(bin),
+            }
+        }
+        SimpleVecHistogram::from_parts(bins, self.bins_cap, moments.clone(), GapDistance)
+    }
+
+    /// Mark the bin at `index` as changed, which makes its distances stale
+    fn touch(&mut self, index: usize) {
+        self.next_generation += 1;
+        self.generations[index] = self.next_generation;
+    }
+
+    /// Candidate for merging the bins at `i` and `j`
+    fn candidate(&self, i: usize, j: usize) -> MergeCandidate {
+        let (left, right) = (i.min(j), i.max(j));
+        MergeCandidate {
+            cost: self.bins[left].distance(&self.bins[right], self.scales),
+            left,
+            right,
+            generations: (self.generations[left], self.generations[right]),
+        }
+    }
+
+    /// Push the distances between the bin at `index` and all other bins
+    fn push_distances(&mut self, index: usize) {
+        for other in 0..self.bins.len() {
+            if other != index {
+                let candidate = self.candidate(index, other);
+                self.distances.push(candidate);
+            }
+        }
+    }
+
+    fn rebuild_distances(&mut self) {
+        self.scales = (scale(&self.moments_x), scale(&self.moments_y));
+        let len = self.bins.len();
+        let candidates = (0..len)
+            .flat_map(|i| (i + 1..len).map(move |j| (i, j)))
+            .map(|(i, j)| self.candidate(i, j))
+            .collect::<Vec<_>>();
+        self.distances = BinaryHeap::from(candidates);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        while self.bins.len() > self.bins_cap && self.bins.len() > 1 {
+            let closest = match self.distances.pop() {
+                Some(closest) => closest,
+                None => {
+                    self.rebuild_distances();
+                    continue;
+                }
+            };
+            let (i, j) = (closest.left, closest.right);
+            let current = j < self.bins.len()
+                && closest.generations == (self.generations[i], self.generations[j]);
+            if !current {
+                continue;
+            }
+
+            // the last bin moves into the place of the removed one
+            let other = self.bins.swap_remove(j);
+            self.generations.swap_remove(j);
+            self.bins[i].merge(&other);
+            self.touch(i);
+            self.push_distances(i);
+            if j < self.bins.len() {
+                self.touch(j);
+                self.push_distances(j);
+            }
+        }
+
+        // drop the stale distances piling up from inserts and merges
+        let pairs = self
+            .bins_cap
+            .saturating_mul(self.bins_cap.saturating_add(1));
+        if self.distances.len() > pairs.saturating_mul(2) {
+            self.rebuild_distances();
+        }
+    }
+
+    /// Append the bins of another histogram and merge down to the bin cap
+    fn extend_bins(&mut self, bins: impl IntoIterator<Item = Bin<V, C>>) {
+        let len = self.bins.len();
+        self.bins.extend(bins);
+        self.generations.resize(self.bins.len(), 0);
+        for index in len..self.bins.len() {
+            self.touch(index);
+        }
+        if self.bins.len() >= self.bins_cap {
+            self.rebuild_distances();
+        }
+        self.shrink_to_fit();
+    }
+}
+
+impl<V, C> EmptyClone for Histogram2d<V, C>
+where
+    V: PartialOrd + OrdSubset + Copy + NumAssign + ToPrimitive,
+    C: Copy + NumAssign + Into<V> + ToPrimitive,
+{
+    fn empty_clone(&self) -> Self {
+        Histogram2d::new(self.bins_cap)
+    }
+}
+
+impl<V, C> MergeRef for Histogram2d<V, C>
+where
+    V: PartialOrd + OrdSubset + Copy + NumAssign + ToPrimitive,
+    C: Copy + NumAssign + Into<V> + ToPrimitive,
+{
+    fn merge_ref(&mut self, other: &Self) {
+        self.total += other.total;
+        self.non_finite += other.non_finite;
+        self.moments_x.merge(&other.moments_x);
+        self.moments_y.merge(&other.moments_y);
+        self.extend_bins(other.bins.iter().cloned());
+    }
+}
+
+impl<V, C> Merge for Histogram2d<V, C>
+where
+    V: PartialOrd + OrdSubset + Copy + NumAssign + ToPrimitive,
+    C: Copy + NumAssign + Into<V> + ToPrimitive,
+{
+    fn merge(&mut self, other: Self) {
+        self.total += other.total;
+        self.non_finite += other.non_finite;
+        self.moments_x.merge(&other.moments_x);
+        self.moments_y.merge(&other.moments_y);
+        self.extend_bins(other.bins);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use traits::{DynamicHistogram, Moments, Quantile};
+
+    #[test]
+    fn insert() {
+        let mut h = Histogram2d::new(3);
+        h.insert_iter(&[(0., 0., 2u32), (10., 10., 1), (0., 1., 1)]);
+        assert_eq!(h.bins().len(), 3);
+
+        // merges the two bins at x = 0, the closest pair
+        h.insert(10., 12., 1);
+        assert_eq!(h.bins().len(), 3);
+        let merged = &h.bins()[0];
+        assert_eq!(merged.count(), 3);
+        assert_eq!(merged.x().left, 0.);
+        assert_eq!(merged.x().right, 0.);
+        assert_eq!(merged.y().left, 0.);
+        assert_eq!(merged.y().right, 1.);
+        assert_eq!(merged.y().sum, 1.);
+
+        // inserts into the merged bin
+        h.insert(0., 0.5, 1);
+        assert_eq!(h.bins()[0].count(), 4);
+        assert_eq!(h.bins().len(), 3);
+        assert_eq!(h.count(), 6);
+    }
+
+    #[test]
+    fn scaled_distance() {
+        // (0, 0) is closer to (1, 0) than to (0, 100) in absolute terms,
+        // but the y axis spans a much larger range than the x axis
+        let mut h = Histogram2d::new(3);
+        h.insert_iter(&[(0., 0., 1u32), (1., 0., 1), (0., 100., 1), (0., 1000., 1)]);
+        let mut bounds = h
+            .bins()
+            .iter()
+            .map(|b| (b.x().left, b.x().right, b.y().left, b.y().right))
+            .collect::<Vec<_>>();
+        bounds.ord_subset_sort();
+        assert_eq!(
+            bounds,
+            vec![(0., 0., 0., 100.), (0., 0., 1000., 1000.), (1., 1., 0., 0.)]
+        );
+    }
+
+    #[test]
+    fn marginals() {
+        let mut h = Histogram2d::new(3);
+        h.insert_iter(&[(5., 0., 1u32), (1., 10., 2), (3., 20., 1), (1., 11., 1)]);
+
+        let x = h.marginal_x();
+        assert_eq!(x.count(), 5);
+        let lefts = x.bins().iter().map(|b| b.left()).collect::<Vec<_>>();
+        assert_eq!(lefts, vec![1., 3., 5.]);
+        assert_eq!(x.quantile(0.), Some(1.));
+        assert_eq!(x.mean(), h.moments_x().mean());
+
+        let y = h.marginal_y();
+        assert_eq!(y.count(), 5);
+        let bounds = y
+            .bins()
+            .iter()
+            .map(|b| (b.left(), b.right()))
+            .collect::<Vec<_>>();
+        assert_eq!(bounds, vec![(0., 0.), (10., 11.), (20., 20.)]);
+        assert_eq!(y.max(), Some(20.));
+    }
+
+    #[test]
+    fn overlapping_marginal() {
+        let mut h = Histogram2d::new(2);
+        // a wide bin at the bottom and a narrow one at the top, which
+        // overlap on the x axis
+        h.insert_iter(&[(0., 0., 1u32), (10., 0., 1), (5., 100., 1), (6., 100., 1)]);
+        assert_eq!(h.bins().len(), 2);
+
+        let mut x = h.marginal_x();
+        let bounds = x
+            .bins()
+            .iter()
+            .map(|b| (b.left(), b.right(), b.count()))
+            .collect::<Vec<_>>();
+        assert_eq!(bounds, vec![(0., 10., 4)]);
+        x.insert(7., 1);
+        assert_eq!(x.bins().len(), 1);
+        assert_eq!(x.count(), 5);
+        assert_eq!(x.quantile(1.), Some(10.));
+
+        // projections with the same left boundary are merged as well
+        let mut h = Histogram2d::new(4);
+        h.insert_iter(&[(1., 0., 1u32), (1., 10., 1), (2., 5., 1)]);
+        let x = h.marginal_x();
+        let bounds = x
+            .bins()
+            .iter()
+            .map(|b| (b.left(), b.right(), b.count()))
+            .collect::<Vec<_>>();
+        assert_eq!(bounds, vec![(1., 1., 2), (2., 2., 1)]);
+    }
+
+    #[test]
+    fn many_bins() {
+        let mut h = Histogram2d::new(50);
+        for i in 0..2000u32 {
+            h.insert(f64::from((i * 7919) % 2000), f64::from(i % 37), 1u32);
+        }
+        assert_eq!(h.bins().len(), 50);
+        assert_eq!(h.bins().iter().map(|b| b.count()).sum::<u32>(), 2000);
+        assert!(h.distances.len() <= 2 * 50 * 51);
+
+        let other = h.clone();
+        h.merge_ref(&other);
+        assert_eq!(h.bins().len(), 50);
+        assert_eq!(h.count(), 4000);
+        assert!(h.distances.len() <= 2 * 50 * 51);
+    }
+
+    #[test]
+    fn ascending() {
+        // an ever growing x range, as with timestamps, doesn't rebuild the
+        // distances on every insert, only when the range has doubled or the
+        // heap is compacted
+        let mut h = Histogram2d::new(30);
+        let mut rebuilds = 0;
+        for i in 0..3000 {
+            let scales = h.scales;
+            h.insert(f64::from(i), f64::from(i % 7), 1u32);
+            if h.bins().len() == 30 {
+                assert!(!rescaled(h.scales.0, scale(h.moments_x())));
+            }
+            if h.scales != scales {
+                rebuilds += 1;
+            }
+        }
+        assert!(rebuilds < 300, "{} rebuilds", rebuilds);
+        assert_eq!(h.bins().len(), 30);
+    }
+
+    #[test]
+    fn merge() {
+        let mut h = Histogram2d::new(2);
+        h.insert_iter(&[(0., 0., 1u32), (10., 10., 1)]);
+        let mut h2 = h.empty_clone();
+        h2.insert_iter(&[(0.5, 0., 1), (10., 9., 2)]);
+
+        h.merge_ref(&h2);
+        assert_eq!(h.count(), 5);
+        assert_eq!(h.bins().len(), 2);
+        let mut counts = h.bins().iter().map(|b| b.count()).collect::<Vec<_>>();
+        counts.sort();
+        assert_eq!(counts, vec![2, 3]);
+
+        h.merge(h2);
+        assert_eq!(h.count(), 8);
+        assert_eq!(h.moments_y().max(), Some(10.));
+    }
+
+    #[test]
+    fn non_finite() {
+        let mut h = Histogram2d::new(2);
+        h.insert_iter(&[
+            (0., 0., 1u32),
+            (f64::NAN, 0., 2),
+            (1., f64::INFINITY, 3),
+            (f64::NEG_INFINITY, f64::NAN, 4),
+        ]);
+        assert_eq!(h.count(), 1);
+        assert_eq!(h.bins().len(), 1);
+        assert_eq!(h.non_finite(), 9);
+        assert_eq!(h.moments_x().max(), Some(0.));
+
+        let copy = h.clone();
+        h.merge(copy);
+        assert_eq!(h.non_finite(), 18);
+        assert_eq!(h.empty_clone().non_finite(), 0);
+    }
+
+    #[test]
+    fn unbounded() {
+        let mut h = Histogram2d::new(usize::MAX);
+        for i in 0..100 {
+            h.insert(f64::from(i % 7), f64::from(i % 3), 1u32);
+        }
+        assert_eq!(h.bins().len(), 21);
+        let mut empty = h.empty_clone();
+        empty.merge_ref(&h);
+        assert_eq!(empty.count(), 100);
+    }
+}
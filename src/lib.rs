@@ -1,6 +1,69 @@
+// the derive of abomonation_derive places its impls inside a named constant
+#![cfg_attr(feature = "abomonation", allow(non_local_definitions))]
+
+#[cfg(feature = "abomonation")]
+extern crate abomonation;
+#[cfg(feature = "abomonation")]
+#[macro_use]
+extern crate abomonation_derive;
 extern crate binary_heap_plus;
+extern crate fnv;
 extern crate num;
 extern crate ord_subset;
+extern crate ordered_float;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+extern crate vec_map;
+
+pub mod concurrent_histogram;
+pub mod d2tree_histogram;
+pub mod d2tree_v2;
+pub mod decaying_histogram;
+mod error;
+pub mod histogram_2d;
+mod non_finite;
+pub mod sets;
+pub mod simple_vec_histogram;
+pub mod traits;
+mod validation;
+pub mod windowed_histogram;
+pub mod wire;
+
+pub use concurrent_histogram::ConcurrentHistogram;
+pub use d2tree_histogram::Histogram as TreeHistogram;
+pub use decaying_histogram::DecayingHistogram;
+pub use error::HistogramError;
+pub use histogram_2d::Histogram2d;
+pub use non_finite::{NonFinite, NonFinitePolicy};
+pub use sets::{
+    BTreeHistogramSet, FnvHistogramSet, HistogramSet, HistogramSetItem, VecHistogramSet,
+};
+pub use simple_vec_histogram::SimpleVecHistogram;
+pub use traits::{
+    BinSummary, BinaryEncoding, CentroidDistance, CountWeightedDistance, Density, DensityGrid,
+    DynamicHistogram, EmptyClone, GapDistance, IterBins, Median, Merge, MergeIter, MergeRef,
+    MergeStrategy, MinimumArea, Moments, Quantile, RangeCount, RunningMoments, TryInsert,
+    TryMedian, TryMerge, Uniform,
+};
+#[cfg(feature = "rayon")]
+pub use traits::{ParInsertIter, ParMerge};
+pub use validation::MAX_BINS;
+pub use windowed_histogram::WindowedHistogram;
+pub use wire::DecodeError;
 
-mod simple_vec_histogram;
-mod traits;
+/// Convenience re-exports of the histogram traits, so that
+/// `use dyn_histogram::prelude::*;` brings all operations into scope
+pub mod prelude {
+    pub use sets::{HistogramSet, HistogramSetItem};
+    pub use traits::{
+        BinaryEncoding, Density, DynamicHistogram, EmptyClone, IterBins, Median, Merge, MergeIter,
+        MergeRef, MergeStrategy, Moments, Quantile, RangeCount, TryInsert, TryMedian, TryMerge,
+        Uniform,
+    };
+    #[cfg(feature = "rayon")]
+    pub use traits::{ParInsertIter, ParMerge};
+}
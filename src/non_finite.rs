@@ -0,0 +1,241 @@
+use error::HistogramError;
+use num::{Bounded, ToPrimitive, Zero};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::ops::{AddAssign, MulAssign, Sub};
+use traits::fallible::check_total;
+
+/// What a histogram does with NaN and infinite values, which have no place
+/// among its bins
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum NonFinitePolicy<V> {
+    /// Fail `try_insert` with `NotANumber` or `Infinite`. Plain `insert`
+    /// can't fail, so it counts the value as with `Count`. This is the
+    /// default.
+    #[default]
+    Reject,
+    /// Count the value in the counter of its kind instead of the bins
+    Count,
+    /// Insert -inf as `min` and +inf as `max`, which must be finite with
+    /// `min <= max`.
+    /// NaN has no place to go and is counted as with `Count`.
+    Clamp { min: V, max: V },
+}
+
+/// Policy of a histogram for non-finite values, with the counts of the NaN
+/// and infinite values that were left out of its bins
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NonFinite<V, C> {
+    policy: NonFinitePolicy<V>,
+    nan: C,
+    pos_inf: C,
+    neg_inf: C,
+}
+
+impl<V, C: Zero> Default for NonFinite<V, C> {
+    fn default() -> Self {
+        NonFinite::empty(NonFinitePolicy::default())
+    }
+}
+
+/// Kind of a value as far as the bins are concerned
+enum Kind {
+    Finite,
+    NaN,
+    PosInf,
+    NegInf,
+}
+
+fn kind<V: ToPrimitive>(value: V) -> Kind {
+    match value.to_f64() {
+        Some(value) if value.is_finite() => Kind::Finite,
+        Some(value) if value == f64::INFINITY => Kind::PosInf,
+        Some(value) if value == f64::NEG_INFINITY => Kind::NegInf,
+        _ => Kind::NaN,
+    }
+}
+
+impl<V: Copy + PartialOrd + ToPrimitive, C: Zero> NonFinite<V, C> {
+    /// Instantiate empty counters for the given policy.
+    ///
+    /// Panics if the bounds of a `Clamp` policy are not finite or `min` is
+    /// greater than `max`, as the bins would then get the values the policy
+    /// is meant to keep out.
+    pub fn new(policy: NonFinitePolicy<V>) -> Self {
+        if let NonFinitePolicy::Clamp { min, max } = policy {
+            let finite = |bound: V| matches!(kind(bound), Kind::Finite);
+            assert!(
+                finite(min) && finite(max) && min <= max,
+                "clamp bounds must be finite with min <= max"
+            );
+        }
+        NonFinite::empty(policy)
+    }
+}
+
+impl<V, C: Zero> NonFinite<V, C> {
+    fn empty(policy: NonFinitePolicy<V>) -> Self {
+        NonFinite {
+            policy,
+            nan: C::zero(),
+            pos_inf: C::zero(),
+            neg_inf: C::zero(),
+        }
+    }
+
+    /// Empty counters with the same policy
+    pub(crate) fn empty_clone(&self) -> Self
+    where
+        V: Clone,
+    {
+        NonFinite::empty(self.policy.clone())
+    }
+
+    /// Counts of NaN, +inf and -inf values, or `None` if there are none
+    pub(crate) fn counts(&self) -> Option<[C; 3]>
+    where
+        C: Copy,
+    {
+        if self.nan.is_zero() && self.pos_inf.is_zero() && self.neg_inf.is_zero() {
+            None
+        } else {
+            Some([self.nan, self.pos_inf, self.neg_inf])
+        }
+    }
+
+    /// Replace the counts of NaN, +inf and -inf values
+    pub(crate) fn set_counts(&mut self, [nan, pos_inf, neg_inf]: [C; 3]) {
+        self.nan = nan;
+        self.pos_inf = pos_inf;
+        self.neg_inf = neg_inf;
+    }
+}
+
+impl<V, C: Copy> NonFinite<V, C> {
+    /// What the histogram does with NaN and infinite values
+    pub fn policy(&self) -> &NonFinitePolicy<V> {
+        &self.policy
+    }
+
+    /// Total count of the NaN values
+    pub fn nan(&self) -> C {
+        self.nan
+    }
+
+    /// Total count of the +inf values that were not clamped
+    pub fn pos_inf(&self) -> C {
+        self.pos_inf
+    }
+
+    /// Total count of the -inf values that were not clamped
+    pub fn neg_inf(&self) -> C {
+        self.neg_inf
+    }
+
+    /// Total count of all values left out of the bins
+    pub fn total(&self) -> C
+    where
+        C: AddAssign,
+    {
+        let mut total = self.nan;
+        total += self.pos_inf;
+        total += self.neg_inf;
+        total
+    }
+}
+
+impl<V, C> NonFinite<V, C>
+where
+    V: Copy + ToPrimitive,
+    C: Copy + PartialOrd + Bounded + Sub<Output = C>,
+{
+    /// Fail if the policy rejects the value, or if the counter it would be
+    /// counted in overflows
+    pub(crate) fn check(&self, value: V, count: C) -> Result<(), HistogramError> {
+        let counter = match (kind(value), &self.policy) {
+            (Kind::Finite, _) => return Ok(()),
+            (Kind::NaN, NonFinitePolicy::Reject) => return Err(HistogramError::NotANumber),
+            (_, NonFinitePolicy::Reject) => return Err(HistogramError::Infinite),
+            (Kind::NaN, _) => self.nan,
+            (_, NonFinitePolicy::Clamp { .. }) => return Ok(()),
+            (Kind::PosInf, _) => self.pos_inf,
+            (Kind::NegInf, _) => self.neg_inf,
+        };
+        check_total(counter, count)
+    }
+
+    /// Fail if adding the counts of another histogram overflows
+    pub(crate) fn check_merge(&self, other: &Self) -> Result<(), HistogramError> {
+        check_total(self.nan, other.nan)?;
+        check_total(self.pos_inf, other.pos_inf)?;
+        check_total(self.neg_inf, other.neg_inf)
+    }
+}
+
+impl<V: Copy + ToPrimitive, C: Copy + AddAssign> NonFinite<V, C> {
+    /// The value to insert into the bins, or `None` if it was counted here
+    pub(crate) fn admit(&mut self, value: V, count: C) -> Option<V> {
+        match (kind(value), &self.policy) {
+            (Kind::Finite, _) => return Some(value),
+            (Kind::PosInf, &NonFinitePolicy::Clamp { max, .. }) => return Some(max),
+            (Kind::NegInf, &NonFinitePolicy::Clamp { min, .. }) => return Some(min),
+            (Kind::NaN, _) => self.nan += count,
+            (Kind::PosInf, _) => self.pos_inf += count,
+            (Kind::NegInf, _) => self.neg_inf += count,
+        }
+        None
+    }
+}
+
+impl<V, C: Copy + AddAssign> NonFinite<V, C> {
+    /// Add the counts of another histogram, keeping this policy
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.nan += other.nan;
+        self.pos_inf += other.pos_inf;
+        self.neg_inf += other.neg_inf;
+    }
+}
+
+impl<V, C: MulAssign + Copy> NonFinite<V, C> {
+    /// Multiply all counts by `factor`
+    pub(crate) fn scale(&mut self, factor: C) {
+        self.nan *= factor;
+        self.pos_inf *= factor;
+        self.neg_inf *= factor;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_bounds() {
+        let clamp = |min, max| NonFinite::<f64, u32>::new(NonFinitePolicy::Clamp { min, max });
+        clamp(-1., 1.);
+        clamp(2., 2.);
+        let invalid = [
+            (f64::NEG_INFINITY, f64::INFINITY),
+            (0., f64::INFINITY),
+            (f64::NAN, 1.),
+            (1., 0.),
+        ];
+        for &(min, max) in &invalid {
+            let result = ::std::panic::catch_unwind(|| clamp(min, max));
+            assert!(result.is_err(), "accepted [{}, {}]", min, max);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "clamp bounds must be finite with min <= max")]
+    fn infinite_clamp() {
+        ::SimpleVecHistogram::<f64, u32>::new(4).with_non_finite_policy(NonFinitePolicy::Clamp {
+            min: f64::NEG_INFINITY,
+            max: f64::INFINITY,
+        });
+    }
+}
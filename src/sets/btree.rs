@@ -1,21 +1,32 @@
 use super::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::collections::btree_map::Entry::*;
-use std::collections::btree_map::Iter;
+use std::collections::btree_map::{Iter, Range};
 use std::collections::BTreeMap;
 use std::iter::FromIterator;
+use std::ops::RangeBounds;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(deserialize = "K: Ord + Deserialize<'de>, H: Deserialize<'de>"))
+)]
 pub struct BTreeHistogramSet<K, H> {
     histograms: BTreeMap<K, H>,
+    template: Option<H>,
 }
 
-#[derive(Clone, Abomonation)]
-pub struct SerializableBTreeHistogramSet<K, H>(Vec<(K, H)>);
+#[derive(Clone)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+pub struct SerializableBTreeHistogramSet<K, H>(Vec<(K, H)>, Option<H>);
 
 impl<K: Ord, H> Default for BTreeHistogramSet<K, H> {
     fn default() -> Self {
         BTreeHistogramSet {
             histograms: BTreeMap::new(),
+            template: None,
         }
     }
 }
@@ -24,7 +35,7 @@ impl<K, H, Hs> From<BTreeHistogramSet<K, H>> for SerializableBTreeHistogramSet<K
 where
     K: DiscreteValue,
     H: HistogramSetItem,
-    Hs: From<H>
+    Hs: From<H>,
 {
     /// Turn this item into a serializable version of itself
     fn from(set: BTreeHistogramSet<K, H>) -> Self {
@@ -33,33 +44,32 @@ where
                 .into_iter()
                 .map(|(k, v)| (k, v.into()))
                 .collect(),
+            set.template.map(Hs::from),
         )
     }
 }
 
-impl<K, H, Hs> Into<BTreeHistogramSet<K, H>> for SerializableBTreeHistogramSet<K, Hs>
+impl<K, H, Hs> From<SerializableBTreeHistogramSet<K, Hs>> for BTreeHistogramSet<K, H>
 where
     K: DiscreteValue,
     H: HistogramSetItem,
-    Hs: Into<H>
+    Hs: Into<H>,
 {
     /// Recover a item from its serializable representation
-    fn into(self) -> BTreeHistogramSet<K, H> {
-        let histograms = self.0.into_iter().map(|(k, ser)| (k, ser.into()));
+    fn from(ser: SerializableBTreeHistogramSet<K, Hs>) -> Self {
+        let histograms = ser.0.into_iter().map(|(k, ser)| (k, ser.into()));
         BTreeHistogramSet {
             histograms: BTreeMap::from_iter(histograms),
+            template: ser.1.map(Hs::into),
         }
     }
 }
 
-impl<K, H> HistogramSetItem for BTreeHistogramSet<K, H>
-where
-    K: DiscreteValue,
-    H: HistogramSetItem,
-{
-    type Serializable = SerializableBTreeHistogramSet<K, H::Serializable>;
-
+impl<K: DiscreteValue, H: HistogramSetItem> Merge for BTreeHistogramSet<K, H> {
     fn merge(&mut self, other: Self) {
+        if self.template.is_none() {
+            self.template = other.template;
+        }
         for (key, value) in other.histograms {
             match self.histograms.entry(key) {
                 Occupied(mut entry) => {
@@ -71,12 +81,17 @@ where
             }
         }
     }
+}
 
-    fn merge_borrowed(&mut self, other: &Self) {
+impl<K: DiscreteValue, H: HistogramSetItem> MergeRef for BTreeHistogramSet<K, H> {
+    fn merge_ref(&mut self, other: &Self) {
+        if self.template.is_none() {
+            self.template = other.template.clone();
+        }
         for (key, value) in &other.histograms {
             match self.histograms.entry(key.clone()) {
                 Occupied(mut entry) => {
-                    entry.get_mut().merge_borrowed(value);
+                    entry.get_mut().merge_ref(value);
                 }
                 Vacant(entry) => {
                     entry.insert(value.clone());
@@ -84,12 +99,21 @@ where
             }
         }
     }
+}
 
+impl<K: Ord, H: Clone> EmptyClone for BTreeHistogramSet<K, H> {
     fn empty_clone(&self) -> Self {
-        Self::default()
+        BTreeHistogramSet {
+            histograms: BTreeMap::new(),
+            template: self.template.clone(),
+        }
     }
 }
 
+impl<K: DiscreteValue, H: HistogramSetItem> HistogramSetItem for BTreeHistogramSet<K, H> {
+    type Serializable = SerializableBTreeHistogramSet<K, H::Serializable>;
+}
+
 impl<K, H> HistogramSet<K, H> for BTreeHistogramSet<K, H>
 where
     K: DiscreteValue,
@@ -106,14 +130,68 @@ where
     fn get_or_insert_with(&mut self, key: &K, insert_fn: impl Fn() -> H) -> &mut H {
         self.histograms.entry(key.clone()).or_insert_with(insert_fn)
     }
+
+    fn get_or_insert(&mut self, key: &K) -> Result<&mut H, HistogramError> {
+        match self.histograms.entry(key.clone()) {
+            Occupied(entry) => Ok(entry.into_mut()),
+            Vacant(entry) => {
+                let template = self.template.as_ref().ok_or(HistogramError::NoTemplate)?;
+                Ok(entry.insert(template.empty_clone()))
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<H> {
+        self.histograms.remove(key)
+    }
+
+    fn retain(&mut self, predicate: impl FnMut(&K, &mut H) -> bool) {
+        self.histograms.retain(predicate)
+    }
+
+    fn len(&self) -> usize {
+        self.histograms.len()
+    }
+
+    fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.histograms.keys().cloned()
+    }
 }
 
 impl<K, H> BTreeHistogramSet<K, H> {
-    pub fn iter(&self) -> Iter<K, H> {
+    /// Instantiate an empty set, which creates histograms for
+    /// new keys as empty clones of `template`
+    pub fn with_template(template: H) -> Self {
+        BTreeHistogramSet {
+            histograms: BTreeMap::new(),
+            template: Some(template),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, H> {
         self.histograms.iter()
     }
 }
 
+impl<K: Ord, H> BTreeHistogramSet<K, H> {
+    /// Iterate over the histograms whose keys lie in `range`, in key order.
+    ///
+    /// With tuple keys, a range over the first element selects all keys
+    /// with a common prefix, e.g. `(label, 0)..(label + 1, 0)`.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, H> {
+        self.histograms.range(range)
+    }
+
+    /// Merge all histograms whose keys lie in `range` into one,
+    /// or `None` if there are none
+    pub fn merge_range<R: RangeBounds<K>>(&self, range: R) -> Option<H>
+    where
+        H: MergeRef + EmptyClone,
+    {
+        self.range(range).map(|(_, histogram)| histogram).merge()
+    }
+}
+
 impl<'a, K, H> IntoIterator for &'a BTreeHistogramSet<K, H> {
     type IntoIter = Iter<'a, K, H>;
     type Item = (&'a K, &'a H);
@@ -1,21 +1,27 @@
 use super::*;
 use fnv::FnvHashMap;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::collections::hash_map::Entry::*;
 use std::collections::hash_map::Iter;
 use std::iter::FromIterator;
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FnvHistogramSet<K: Eq + Hash, H> {
     histograms: FnvHashMap<K, H>,
+    template: Option<H>,
 }
 
-#[derive(Clone, Abomonation)]
-pub struct SerializableFnvHistogramSet<K, H>(Vec<(K, H)>);
+#[derive(Clone)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+pub struct SerializableFnvHistogramSet<K, H>(Vec<(K, H)>, Option<H>);
 
 impl<K: Eq + Hash, H> Default for FnvHistogramSet<K, H> {
     fn default() -> Self {
         FnvHistogramSet {
             histograms: FnvHashMap::default(),
+            template: None,
         }
     }
 }
@@ -24,7 +30,7 @@ impl<K, H, Hs> From<FnvHistogramSet<K, H>> for SerializableFnvHistogramSet<K, Hs
 where
     K: DiscreteValue,
     H: HistogramSetItem,
-    Hs: From<H>
+    Hs: From<H>,
 {
     /// Turn this item into a serializable version of itself
     fn from(set: FnvHistogramSet<K, H>) -> Self {
@@ -33,33 +39,32 @@ where
                 .into_iter()
                 .map(|(k, v)| (k, Hs::from(v)))
                 .collect(),
+            set.template.map(Hs::from),
         )
     }
 }
 
-impl<K, H, Hs> Into<FnvHistogramSet<K, H>> for SerializableFnvHistogramSet<K, Hs>
+impl<K, H, Hs> From<SerializableFnvHistogramSet<K, Hs>> for FnvHistogramSet<K, H>
 where
     K: DiscreteValue,
     H: HistogramSetItem,
     Hs: Into<H>,
 {
     /// Recover a item from its serializable representation
-    fn into(self) -> FnvHistogramSet<K, H> {
-        let histograms = self.0.into_iter().map(|(k, ser)| (k, ser.into()));
+    fn from(ser: SerializableFnvHistogramSet<K, Hs>) -> Self {
+        let histograms = ser.0.into_iter().map(|(k, ser)| (k, ser.into()));
         FnvHistogramSet {
             histograms: FnvHashMap::from_iter(histograms),
+            template: ser.1.map(Hs::into),
         }
     }
 }
 
-impl<K, H> HistogramSetItem for FnvHistogramSet<K, H>
-where
-    K: DiscreteValue,
-    H: HistogramSetItem,
-{
-    type Serializable = SerializableFnvHistogramSet<K, H::Serializable>;
-
+impl<K: DiscreteValue, H: HistogramSetItem> Merge for FnvHistogramSet<K, H> {
     fn merge(&mut self, other: Self) {
+        if self.template.is_none() {
+            self.template = other.template;
+        }
         for (key, value) in other.histograms {
             match self.histograms.entry(key) {
                 Occupied(mut entry) => {
@@ -71,12 +76,17 @@ where
             }
         }
     }
+}
 
-    fn merge_borrowed(&mut self, other: &Self) {
+impl<K: DiscreteValue, H: HistogramSetItem> MergeRef for FnvHistogramSet<K, H> {
+    fn merge_ref(&mut self, other: &Self) {
+        if self.template.is_none() {
+            self.template = other.template.clone();
+        }
         for (key, value) in &other.histograms {
             match self.histograms.entry(key.clone()) {
                 Occupied(mut entry) => {
-                    entry.get_mut().merge_borrowed(value);
+                    entry.get_mut().merge_ref(value);
                 }
                 Vacant(entry) => {
                     entry.insert(value.clone());
@@ -84,12 +94,21 @@ where
             }
         }
     }
+}
 
+impl<K: Eq + Hash, H: Clone> EmptyClone for FnvHistogramSet<K, H> {
     fn empty_clone(&self) -> Self {
-        Self::default()
+        FnvHistogramSet {
+            histograms: FnvHashMap::default(),
+            template: self.template.clone(),
+        }
     }
 }
 
+impl<K: DiscreteValue, H: HistogramSetItem> HistogramSetItem for FnvHistogramSet<K, H> {
+    type Serializable = SerializableFnvHistogramSet<K, H::Serializable>;
+}
+
 impl<K, H> HistogramSet<K, H> for FnvHistogramSet<K, H>
 where
     K: DiscreteValue,
@@ -106,10 +125,45 @@ where
     fn get_or_insert_with(&mut self, key: &K, insert_fn: impl Fn() -> H) -> &mut H {
         self.histograms.entry(key.clone()).or_insert_with(insert_fn)
     }
+
+    fn get_or_insert(&mut self, key: &K) -> Result<&mut H, HistogramError> {
+        match self.histograms.entry(key.clone()) {
+            Occupied(entry) => Ok(entry.into_mut()),
+            Vacant(entry) => {
+                let template = self.template.as_ref().ok_or(HistogramError::NoTemplate)?;
+                Ok(entry.insert(template.empty_clone()))
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<H> {
+        self.histograms.remove(key)
+    }
+
+    fn retain(&mut self, predicate: impl FnMut(&K, &mut H) -> bool) {
+        self.histograms.retain(predicate)
+    }
+
+    fn len(&self) -> usize {
+        self.histograms.len()
+    }
+
+    fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.histograms.keys().cloned()
+    }
 }
 
 impl<K: Eq + Hash, H> FnvHistogramSet<K, H> {
-    pub fn iter(&self) -> Iter<K, H> {
+    /// Instantiate an empty set, which creates histograms for
+    /// new keys as empty clones of `template`
+    pub fn with_template(template: H) -> Self {
+        FnvHistogramSet {
+            histograms: FnvHashMap::default(),
+            template: Some(template),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, H> {
         self.histograms.iter()
     }
 }
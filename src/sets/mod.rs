@@ -0,0 +1,256 @@
+//! Collections keeping one histogram per key, e.g. per feature or label
+
+use error::HistogramError;
+use num::traits::NumAssign;
+use num::ToPrimitive;
+use std::hash::Hash;
+use traits::{DynamicHistogram, EmptyClone, Median, Merge, MergeIter, MergeRef, Quantile};
+
+mod btree;
+mod fnv;
+mod vec;
+
+pub use self::btree::{BTreeHistogramSet, SerializableBTreeHistogramSet};
+pub use self::fnv::{FnvHistogramSet, SerializableFnvHistogramSet};
+pub use self::vec::{SerializableVecHistogramSet, VecHistogramSet};
+
+/// A type that can be used as the key of a histogram set
+pub trait DiscreteValue: Ord + Hash + Clone {}
+
+impl<T: Ord + Hash + Clone> DiscreteValue for T {}
+
+/// A type that can be used as the value of the histograms in a set
+pub trait ContinuousValue: PartialOrd + Copy + NumAssign + ToPrimitive {}
+
+impl<T: PartialOrd + Copy + NumAssign + ToPrimitive> ContinuousValue for T {}
+
+/// A histogram, or a set of histograms, that can be kept in a histogram set
+pub trait HistogramSetItem: Clone + Merge + MergeRef + EmptyClone {
+    /// Representation of this item that can be exchanged between workers
+    type Serializable: From<Self> + Into<Self>;
+}
+
+/// A collection of histograms, indexed by key
+pub trait HistogramSet<K, H> {
+    /// Histogram stored for the given key
+    fn get(&self, key: &K) -> Option<&H>;
+
+    /// Mutable histogram stored for the given key
+    fn get_mut(&mut self, key: &K) -> Option<&mut H>;
+
+    /// Histogram stored for the given key, inserting the result of
+    /// `insert_fn` if there is none yet
+    fn get_or_insert_with(&mut self, key: &K, insert_fn: impl Fn() -> H) -> &mut H;
+
+    /// Histogram stored for the given key, inserting an empty clone of the
+    /// template of this set if there is none yet.
+    ///
+    /// Fails with `NoTemplate` if a histogram has to be inserted and the set
+    /// has no template.
+    fn get_or_insert(&mut self, key: &K) -> Result<&mut H, HistogramError>;
+
+    /// Remove the histogram stored for the given key and return it
+    fn remove(&mut self, key: &K) -> Option<H>;
+
+    /// Keep only the histograms for which `predicate` returns true
+    fn retain(&mut self, predicate: impl FnMut(&K, &mut H) -> bool);
+
+    /// Number of histograms in this set
+    fn len(&self) -> usize;
+
+    /// Whether this set holds no histograms
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keys of all histograms in this set
+    fn keys(&self) -> impl Iterator<Item = K> + '_;
+
+    /// Insert a data point into the histogram for the given key, creating
+    /// the histogram from the template of this set if necessary.
+    ///
+    /// Fails with `NoTemplate` without inserting if a histogram has to be
+    /// created and the set has no template.
+    fn insert<V, C>(&mut self, key: &K, value: V, count: C) -> Result<(), HistogramError>
+    where
+        H: DynamicHistogram<V, C>,
+    {
+        self.get_or_insert(key)?.insert(value, count);
+        Ok(())
+    }
+
+    /// Estimate the `q`-quantile of the histogram for the given key
+    fn quantile<V>(&self, key: &K, q: f64) -> Option<V>
+    where
+        H: Quantile<V>,
+    {
+        self.get(key)?.quantile(q)
+    }
+
+    /// Estimate the median of the histogram for the given key
+    fn median<V>(&self, key: &K) -> Option<V>
+    where
+        H: Median<V>,
+    {
+        self.get(key)?.median()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use d2tree_histogram::{Histogram, SerializableHistogram};
+    use simple_vec_histogram::SimpleVecHistogram;
+    use traits::DynamicHistogram;
+
+    #[test]
+    fn btree_set() {
+        let mut set = BTreeHistogramSet::default();
+        for (key, value) in &[("a", 1.), ("b", 2.), ("a", 3.)] {
+            set.get_or_insert_with(key, || SimpleVecHistogram::new(4))
+                .insert(*value, 1u32);
+        }
+        let mut other = set.empty_clone();
+        other
+            .get_or_insert_with(&"c", || SimpleVecHistogram::new(4))
+            .insert(4., 1);
+        other
+            .get_or_insert_with(&"a", || SimpleVecHistogram::new(4))
+            .insert(5., 2);
+
+        set.merge_ref(&other);
+        let counts = set.iter().map(|(k, h)| (*k, h.count())).collect::<Vec<_>>();
+        assert_eq!(counts, vec![("a", 4), ("b", 1), ("c", 1)]);
+        set.merge(other);
+        assert_eq!(set.get(&"a").map(|h| h.count()), Some(6));
+        assert!(set.get(&"d").is_none());
+    }
+
+    #[test]
+    fn vec_set() {
+        let mut set = VecHistogramSet::default();
+        set.get_or_insert_with(&3, || Histogram::new(4))
+            .insert(1., 1u32);
+        set.get_mut(&3).unwrap().insert(2., 1);
+        let mut other = VecHistogramSet::default();
+        other
+            .get_or_insert_with(&0, || Histogram::new(4))
+            .insert(1., 1u32);
+
+        set.merge(other);
+        let counts = set.iter().map(|(k, h)| (k, h.count())).collect::<Vec<_>>();
+        assert_eq!(counts, vec![(0, 1), (3, 2)]);
+
+        // tree histograms are exchanged in their serializable form
+        let serializable: SerializableVecHistogramSet<SerializableHistogram<f64, u32>> = set.into();
+        let restored: VecHistogramSet<Histogram<f64, u32>> = serializable.into();
+        assert_eq!(restored.get(&3).map(|h| h.count()), Some(2));
+    }
+
+    fn keyed_queries<S: HistogramSet<u8, SimpleVecHistogram<f64, u32>> + EmptyClone>(mut set: S) {
+        for (key, value) in &[(3, 1.), (1, 2.), (3, 3.), (3, 5.), (2, 4.)] {
+            set.insert(key, *value, 1).unwrap();
+        }
+        assert_eq!(set.len(), 3);
+        let mut keys = set.keys().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec![1, 2, 3]);
+        assert_eq!(set.median(&3), Some(3.));
+        assert_eq!(set.quantile(&1, 0.), Some(2.));
+        assert_eq!(set.quantile(&7, 0.5), None);
+
+        assert_eq!(set.remove(&2).map(|h| h.count()), Some(1));
+        assert_eq!(set.remove(&2), None);
+        set.retain(|_, h| h.count() > 1);
+        assert_eq!(set.keys().collect::<Vec<_>>(), vec![3]);
+
+        // empty clones keep creating histograms from the template
+        let mut empty = set.empty_clone();
+        assert!(empty.is_empty());
+        empty.insert(&9, 1., 2).unwrap();
+        assert_eq!(empty.get(&9).map(|h| h.count()), Some(2));
+    }
+
+    #[test]
+    fn keyed_insert() {
+        let template = SimpleVecHistogram::new(4);
+        keyed_queries(BTreeHistogramSet::with_template(template.clone()));
+        keyed_queries(FnvHistogramSet::with_template(template.clone()));
+
+        let mut set = VecHistogramSet::with_template(template);
+        set.insert(&3, 1., 1u32).unwrap();
+        set.insert(&1, 2., 1).unwrap();
+        set.insert(&3, 4., 1).unwrap();
+        assert_eq!(set.keys().collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(set.quantile(&3, 1.), Some(4.));
+        set.retain(|key, _| *key > 1);
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.remove(&3).map(|h| h.count()), Some(2));
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn insert_without_template() {
+        let mut set: BTreeHistogramSet<u8, SimpleVecHistogram<f64, u32>> = Default::default();
+        assert_eq!(set.insert(&1, 1., 1), Err(HistogramError::NoTemplate));
+        assert!(set.is_empty());
+
+        // existing histograms don't need the template
+        set.get_or_insert_with(&1, || SimpleVecHistogram::new(4));
+        assert_eq!(set.insert(&1, 1., 1), Ok(()));
+        assert_eq!(set.get_or_insert(&1).map(|h| h.count()), Ok(1));
+        assert!(VecHistogramSet::<SimpleVecHistogram<f64, u32>>::default()
+            .get_or_insert(&0)
+            .is_err());
+        assert!(
+            FnvHistogramSet::<u8, SimpleVecHistogram<f64, u32>>::default()
+                .get_or_insert(&0)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn btree_range() {
+        // per-minute histograms
+        let mut set = BTreeHistogramSet::with_template(SimpleVecHistogram::new(8));
+        for minute in 0..10u32 {
+            set.insert(&minute, f64::from(minute), 1 + minute).unwrap();
+        }
+
+        let keys = set.range(3..6).map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec![3, 4, 5]);
+        let last = set.merge_range(7..).unwrap();
+        assert_eq!(last.count(), 8 + 9 + 10);
+        assert_eq!(last.quantile(0.), Some(7.));
+        assert_eq!(last.quantile(1.), Some(9.));
+        assert_eq!(set.merge_range(..=0).map(|h| h.count()), Some(1));
+        assert!(set.merge_range(20..30).is_none());
+    }
+
+    #[test]
+    fn nested_fnv_set() {
+        let mut set: FnvHistogramSet<u8, BTreeHistogramSet<u8, SimpleVecHistogram<f64, u32>>> =
+            FnvHistogramSet::default();
+        set.get_or_insert_with(&1, BTreeHistogramSet::default)
+            .get_or_insert_with(&2, || SimpleVecHistogram::new(4))
+            .insert(1., 1);
+        let copy = set.clone();
+        set.merge_ref(&copy);
+        let inner = set.get(&1).and_then(|inner| inner.get(&2));
+        assert_eq!(inner.map(|h| h.count()), Some(2));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        use serde_json;
+
+        let mut set = BTreeHistogramSet::default();
+        set.get_or_insert_with(&7u32, || SimpleVecHistogram::new(4))
+            .insert(1., 2u32);
+        let json = serde_json::to_string(&set).unwrap();
+        let restored: BTreeHistogramSet<u32, SimpleVecHistogram<f64, u32>> =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.get(&7), set.get(&7));
+    }
+}
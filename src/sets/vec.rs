@@ -1,19 +1,25 @@
 use super::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::iter::FromIterator;
 use vec_map::{Entry::*, Iter, VecMap};
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct VecHistogramSet<H> {
     histograms: VecMap<H>,
+    template: Option<H>,
 }
 
-#[derive(Abomonation, Clone)]
-pub struct SerializableVecHistogramSet<H>(Vec<(usize, H)>);
+#[derive(Clone)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+pub struct SerializableVecHistogramSet<H>(Vec<(usize, H)>, Option<H>);
 
 impl<H> Default for VecHistogramSet<H> {
     fn default() -> Self {
         VecHistogramSet {
             histograms: VecMap::default(),
+            template: None,
         }
     }
 }
@@ -30,31 +36,31 @@ where
                 .into_iter()
                 .map(|(k, v)| (k, Hs::from(v)))
                 .collect(),
+            set.template.map(Hs::from),
         )
     }
 }
 
-impl<H, Hs> Into<VecHistogramSet<H>> for SerializableVecHistogramSet<Hs>
+impl<H, Hs> From<SerializableVecHistogramSet<Hs>> for VecHistogramSet<H>
 where
     H: HistogramSetItem,
-    Hs: Into<H>
+    Hs: Into<H>,
 {
     /// Recover a item from its serializable representation
-    fn into(self) -> VecHistogramSet<H> {
-        let histograms = self.0.into_iter().map(|(k, ser)| (k, ser.into()));
+    fn from(ser: SerializableVecHistogramSet<Hs>) -> Self {
+        let histograms = ser.0.into_iter().map(|(k, ser)| (k, ser.into()));
         VecHistogramSet {
             histograms: VecMap::from_iter(histograms),
+            template: ser.1.map(Hs::into),
         }
     }
 }
 
-impl<H> HistogramSetItem for VecHistogramSet<H>
-where
-    H: HistogramSetItem,
-{
-    type Serializable = SerializableVecHistogramSet<H::Serializable>;
-
+impl<H: HistogramSetItem> Merge for VecHistogramSet<H> {
     fn merge(&mut self, other: Self) {
+        if self.template.is_none() {
+            self.template = other.template;
+        }
         for (key, value) in other.histograms {
             match self.histograms.entry(key) {
                 Occupied(mut entry) => {
@@ -66,12 +72,17 @@ where
             }
         }
     }
+}
 
-    fn merge_borrowed(&mut self, other: &Self) {
+impl<H: HistogramSetItem> MergeRef for VecHistogramSet<H> {
+    fn merge_ref(&mut self, other: &Self) {
+        if self.template.is_none() {
+            self.template = other.template.clone();
+        }
         for (key, value) in other.histograms.iter() {
             match self.histograms.entry(key) {
                 Occupied(mut entry) => {
-                    entry.get_mut().merge_borrowed(value);
+                    entry.get_mut().merge_ref(value);
                 }
                 Vacant(entry) => {
                     entry.insert(value.clone());
@@ -79,12 +90,21 @@ where
             }
         }
     }
+}
 
+impl<H: Clone> EmptyClone for VecHistogramSet<H> {
     fn empty_clone(&self) -> Self {
-        Self::default()
+        VecHistogramSet {
+            histograms: VecMap::default(),
+            template: self.template.clone(),
+        }
     }
 }
 
+impl<H: HistogramSetItem> HistogramSetItem for VecHistogramSet<H> {
+    type Serializable = SerializableVecHistogramSet<H::Serializable>;
+}
+
 impl<H> HistogramSet<usize, H> for VecHistogramSet<H>
 where
     H: HistogramSetItem,
@@ -98,12 +118,48 @@ where
     }
 
     fn get_or_insert_with(&mut self, key: &usize, insert_fn: impl Fn() -> H) -> &mut H {
-        self.histograms.entry(key.clone()).or_insert_with(insert_fn)
+        self.histograms.entry(*key).or_insert_with(insert_fn)
+    }
+
+    fn get_or_insert(&mut self, key: &usize) -> Result<&mut H, HistogramError> {
+        match self.histograms.entry(*key) {
+            Occupied(entry) => Ok(entry.into_mut()),
+            Vacant(entry) => {
+                let template = self.template.as_ref().ok_or(HistogramError::NoTemplate)?;
+                Ok(entry.insert(template.empty_clone()))
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &usize) -> Option<H> {
+        self.histograms.remove(*key)
+    }
+
+    fn retain(&mut self, mut predicate: impl FnMut(&usize, &mut H) -> bool) {
+        self.histograms
+            .retain(|key, histogram| predicate(&key, histogram))
+    }
+
+    fn len(&self) -> usize {
+        self.histograms.len()
+    }
+
+    fn keys(&self) -> impl Iterator<Item = usize> + '_ {
+        self.histograms.keys()
     }
 }
 
 impl<H> VecHistogramSet<H> {
-    pub fn iter(&self) -> Iter<H> {
+    /// Instantiate an empty set, which creates histograms for
+    /// new keys as empty clones of `template`
+    pub fn with_template(template: H) -> Self {
+        VecHistogramSet {
+            histograms: VecMap::default(),
+            template: Some(template),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, H> {
         self.histograms.iter()
     }
 }
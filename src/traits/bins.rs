@@ -0,0 +1,30 @@
+use num::ToPrimitive;
+
+/// Boundaries, number of points, sum and sum of squares of the points
+/// of a single bin, independent of how a histogram stores its bins
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BinSummary<V, C> {
+    pub left: V,
+    pub right: V,
+    pub count: C,
+    pub sum: V,
+    pub sum_sq: V,
+}
+
+/// Read access to the bins of a histogram, which the
+/// distribution estimates are built on
+pub trait IterBins {
+    /// Type of the values in this histogram
+    type Value;
+    /// Type of the bin counts in this histogram
+    type Count;
+
+    /// Iterate over the bins of this histogram in ascending order
+    fn iter_bins(&self) -> impl Iterator<Item = BinSummary<Self::Value, Self::Count>> + '_;
+}
+
+/// Convert a bin value or count to `f64` for the distribution estimates,
+/// mapping unrepresentable values to NaN
+pub(crate) fn to_f64<T: ToPrimitive>(value: T) -> f64 {
+    value.to_f64().unwrap_or(f64::NAN)
+}
@@ -0,0 +1,150 @@
+use super::bins::to_f64;
+use super::IterBins;
+use num::{NumCast, ToPrimitive};
+use std::marker::PhantomData;
+
+/// Probability density estimates.
+///
+/// The range between the first and the last bin is split into cells at the
+/// midpoints of the gaps between neighbouring bins, so each cell contains
+/// exactly one bin. The points of a bin are spread uniformly over its cell,
+/// which gives bins holding a single distinct value a finite density as long
+/// as the histogram has more than one bin. The density is the count of a
+/// cell divided by its width and the total count, so it integrates to one.
+pub trait Density<V> {
+    /// Estimate the probability density at `value`.
+    ///
+    /// Returns `0` outside of the range of the histogram or for an empty
+    /// histogram, and infinity at the single value of a histogram that
+    /// contains only one distinct value.
+    fn pdf(&self, value: V) -> f64;
+
+    /// Sample the probability density at `n_points` evenly spaced positions
+    /// from `from` to `to` (both inclusive)
+    fn density_grid(&self, from: V, to: V, n_points: usize) -> DensityGrid<V>;
+}
+
+/// Iterator over `(position, density)` pairs, created by `Density::density_grid`
+#[derive(Clone, Debug)]
+pub struct DensityGrid<V> {
+    cells: Vec<Cell>,
+    cell: usize,
+    from: f64,
+    step: f64,
+    index: usize,
+    n_points: usize,
+    _value: PhantomData<V>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Cell {
+    start: f64,
+    end: f64,
+    density: f64,
+}
+
+impl Cell {
+    fn density_at(&self, value: f64) -> f64 {
+        if value >= self.start && value <= self.end {
+            self.density
+        } else {
+            0.
+        }
+    }
+}
+
+/// Split the range of a histogram into one cell per bin
+fn cells<H>(histogram: &H) -> Vec<Cell>
+where
+    H: IterBins,
+    H::Value: ToPrimitive,
+    H::Count: ToPrimitive,
+{
+    let bins = histogram
+        .iter_bins()
+        .map(|bin| (to_f64(bin.left), to_f64(bin.right), to_f64(bin.count)))
+        .filter(|&(_, _, count)| count > 0.)
+        .collect::<Vec<_>>();
+    let total: f64 = bins.iter().map(|&(_, _, count)| count).sum();
+
+    let mut cells = Vec::with_capacity(bins.len());
+    for (i, &(left, right, count)) in bins.iter().enumerate() {
+        let start = match i {
+            0 => left,
+            _ => (bins[i - 1].1 + left) / 2.,
+        };
+        let end = match bins.get(i + 1) {
+            Some(&(next_left, _, _)) => (right + next_left) / 2.,
+            None => right,
+        };
+        let density = if end > start {
+            count / (total * (end - start))
+        } else {
+            f64::INFINITY
+        };
+        cells.push(Cell {
+            start,
+            end,
+            density,
+        });
+    }
+    cells
+}
+
+impl<H> Density<H::Value> for H
+where
+    H: IterBins,
+    H::Value: ToPrimitive,
+    H::Count: ToPrimitive,
+{
+    fn pdf(&self, value: H::Value) -> f64 {
+        let value = to_f64(value);
+        cells(self)
+            .iter()
+            .find(|cell| value <= cell.end)
+            .map_or(0., |cell| cell.density_at(value))
+    }
+
+    fn density_grid(&self, from: H::Value, to: H::Value, n_points: usize) -> DensityGrid<H::Value> {
+        let from = to_f64(from);
+        let step = if n_points > 1 {
+            (to_f64(to) - from) / (n_points - 1) as f64
+        } else {
+            0.
+        };
+        DensityGrid {
+            cells: cells(self),
+            cell: 0,
+            from,
+            step,
+            index: 0,
+            n_points,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<V: NumCast> Iterator for DensityGrid<V> {
+    type Item = (V, f64);
+
+    fn next(&mut self) -> Option<(V, f64)> {
+        if self.index >= self.n_points {
+            return None;
+        }
+        let position = self.from + self.step * self.index as f64;
+        self.index += 1;
+
+        // positions only move forward, unless the grid runs backwards
+        if self.step < 0. {
+            self.cell = 0;
+        }
+        while self.cell < self.cells.len() && self.cells[self.cell].end < position {
+            self.cell += 1;
+        }
+        let density = self
+            .cells
+            .get(self.cell)
+            .map_or(0., |cell| cell.density_at(position));
+        Some((NumCast::from(position)?, density))
+    }
+}
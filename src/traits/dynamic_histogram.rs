@@ -7,7 +7,12 @@ pub trait DynamicHistogram<T, C> {
     /// Instantiate a histogram with the given number of maximum bins
     fn new(n_bins: usize) -> Self;
 
-    /// Insert a new data point into this histogram
+    /// Insert a new data point into this histogram.
+    ///
+    /// `count` is the weight of the data point. With floating point counts
+    /// it may be fractional, e.g. for importance weights or sampling rate
+    /// corrections. Points with a weight that is not positive (zero,
+    /// negative or NaN) are ignored.
     fn insert(&mut self, value: T, count: C);
 
     fn insert_iter<'a>(&mut self, values: impl IntoIterator<Item = &'a (T, C)>)
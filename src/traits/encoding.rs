@@ -0,0 +1,14 @@
+use wire::DecodeError;
+
+/// Compact binary representation of a histogram, in the versioned
+/// format described in the [`wire`](../wire/index.html) module
+pub trait BinaryEncoding: Sized {
+    /// Encode this histogram, including its bin cap and moments but not
+    /// its merge strategy
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Decode a histogram written by `to_bytes`, with the default merge
+    /// strategy. Fails on truncated or malformed payloads, payloads of
+    /// another format version and payloads of other value or count types.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError>;
+}
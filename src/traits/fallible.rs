@@ -0,0 +1,80 @@
+use super::bins::to_f64;
+use super::{DynamicHistogram, IterBins, Median};
+use error::HistogramError;
+use num::{Bounded, ToPrimitive};
+use std::ops::Sub;
+
+/// Insertion that reports invalid input instead of ignoring it
+pub trait TryInsert<V, C>: DynamicHistogram<V, C> {
+    /// Insert a new data point like `insert`, but fail without changing
+    /// the histogram if the histogram rejects the value, the count is NaN,
+    /// not positive or can't be converted, the total count would overflow
+    /// or the histogram can't hold any bins.
+    ///
+    /// NaN and infinite values are rejected unless the
+    /// [`NonFinitePolicy`](../enum.NonFinitePolicy.html) of the histogram
+    /// says otherwise.
+    fn try_insert(&mut self, value: V, count: C) -> Result<(), HistogramError>;
+}
+
+/// Merging that reports an overflowing total count instead of panicking
+pub trait TryMerge: Sized {
+    /// Merge another histogram into this one, failing without changing this
+    /// histogram if the total count would overflow or this histogram can't
+    /// hold any bins
+    fn try_merge(&mut self, other: Self) -> Result<(), HistogramError>;
+}
+
+/// Median that tells an empty histogram apart from a failed estimate
+pub trait TryMedian<V> {
+    /// Estimate the median, failing with `Empty` if there are no data
+    /// points and with `CastFailed` if the estimate can't be represented
+    /// as a value
+    fn try_median(&self) -> Result<V, HistogramError>;
+}
+
+impl<H> TryMedian<H::Value> for H
+where
+    H: IterBins + Median<H::Value>,
+    H::Count: ToPrimitive,
+{
+    fn try_median(&self) -> Result<H::Value, HistogramError> {
+        let total: f64 = self.iter_bins().map(|bin| to_f64(bin.count)).sum();
+        if total.is_nan() {
+            return Err(HistogramError::CastFailed);
+        }
+        if total <= 0. {
+            return Err(HistogramError::Empty);
+        }
+        self.median().ok_or(HistogramError::CastFailed)
+    }
+}
+
+/// Check a count and the capacity of a histogram before inserting
+pub(crate) fn check_count<C>(count: C, total: C, n_bins: usize) -> Result<(), HistogramError>
+where
+    C: Copy + PartialOrd + Bounded + Sub<Output = C> + ToPrimitive,
+{
+    if n_bins == 0 {
+        return Err(HistogramError::ZeroCapacity);
+    }
+    match count.to_f64() {
+        None => return Err(HistogramError::CastFailed),
+        Some(weight) if weight.is_nan() => return Err(HistogramError::NotANumber),
+        Some(weight) if weight <= 0. => return Err(HistogramError::NonPositiveCount),
+        Some(_) => {}
+    }
+    check_total(total, count)
+}
+
+/// Check that adding `count` to the non-negative `total` does not overflow
+pub(crate) fn check_total<C>(total: C, count: C) -> Result<(), HistogramError>
+where
+    C: Copy + PartialOrd + Bounded + Sub<Output = C>,
+{
+    if count > C::max_value() - total {
+        Err(HistogramError::Overflow)
+    } else {
+        Ok(())
+    }
+}
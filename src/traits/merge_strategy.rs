@@ -0,0 +1,75 @@
+use super::bins::to_f64;
+use super::BinSummary;
+use num::ToPrimitive;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Decides which pair of neighbouring bins is merged first
+/// when a histogram exceeds its maximum number of bins
+pub trait MergeStrategy<V, C> {
+    /// Cost of merging two neighbouring bins. The pair
+    /// with the lowest cost is merged first.
+    fn cost(&self, left: &BinSummary<V, C>, right: &BinSummary<V, C>) -> f64;
+}
+
+/// Merge the bins with the smallest gap between the right boundary
+/// of the left bin and the left boundary of the right bin.
+///
+/// Keeps the bin boundaries tight, but collapses dense regions
+/// where many bins are close together.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GapDistance;
+
+/// Merge the bins whose centroids (`sum / count`) are closest,
+/// as in Ben-Haim & Tom-Tov's streaming histogram
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CentroidDistance;
+
+/// Merge the bins with the smallest centroid distance multiplied by their
+/// combined count, which keeps heavy bins apart and resolves dense regions
+/// at the expense of sparse tails
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CountWeightedDistance;
+
+/// Merge the bins whose merged bin covers the smallest area, i.e. width of
+/// the merged bin times the combined count. This minimises the area under
+/// the histogram that is smeared out by a merge, which keeps both narrow
+/// peaks and sparse tails.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MinimumArea;
+
+fn centroid<V: Copy + ToPrimitive, C: Copy + ToPrimitive>(bin: &BinSummary<V, C>) -> f64 {
+    to_f64(bin.sum) / to_f64(bin.count)
+}
+
+impl<V: Copy + ToPrimitive, C> MergeStrategy<V, C> for GapDistance {
+    fn cost(&self, left: &BinSummary<V, C>, right: &BinSummary<V, C>) -> f64 {
+        to_f64(right.left) - to_f64(left.right)
+    }
+}
+
+impl<V: Copy + ToPrimitive, C: Copy + ToPrimitive> MergeStrategy<V, C> for CentroidDistance {
+    fn cost(&self, left: &BinSummary<V, C>, right: &BinSummary<V, C>) -> f64 {
+        centroid(right) - centroid(left)
+    }
+}
+
+impl<V: Copy + ToPrimitive, C: Copy + ToPrimitive> MergeStrategy<V, C> for CountWeightedDistance {
+    fn cost(&self, left: &BinSummary<V, C>, right: &BinSummary<V, C>) -> f64 {
+        (centroid(right) - centroid(left)) * (to_f64(left.count) + to_f64(right.count))
+    }
+}
+
+impl<V: Copy + ToPrimitive, C: Copy + ToPrimitive> MergeStrategy<V, C> for MinimumArea {
+    fn cost(&self, left: &BinSummary<V, C>, right: &BinSummary<V, C>) -> f64 {
+        (to_f64(right.right) - to_f64(left.left)) * (to_f64(left.count) + to_f64(right.count))
+    }
+}
@@ -1,9 +1,42 @@
+mod bins;
+mod density;
 mod dynamic_histogram;
+mod encoding;
+pub(crate) mod fallible;
+mod merge_strategy;
+mod moments;
 mod operations;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod quantile;
+mod sum;
+mod uniform;
 
+pub use self::bins::BinSummary;
+pub use self::bins::IterBins;
+pub use self::density::Density;
+pub use self::density::DensityGrid;
 pub use self::dynamic_histogram::DynamicHistogram;
+pub use self::encoding::BinaryEncoding;
+pub use self::fallible::TryInsert;
+pub use self::fallible::TryMedian;
+pub use self::fallible::TryMerge;
+pub use self::merge_strategy::CentroidDistance;
+pub use self::merge_strategy::CountWeightedDistance;
+pub use self::merge_strategy::GapDistance;
+pub use self::merge_strategy::MergeStrategy;
+pub use self::merge_strategy::MinimumArea;
+pub use self::moments::Moments;
+pub use self::moments::RunningMoments;
 pub use self::operations::EmptyClone;
 pub use self::operations::Median;
 pub use self::operations::Merge;
 pub use self::operations::MergeIter;
 pub use self::operations::MergeRef;
+#[cfg(feature = "rayon")]
+pub use self::parallel::ParInsertIter;
+#[cfg(feature = "rayon")]
+pub use self::parallel::ParMerge;
+pub use self::quantile::Quantile;
+pub use self::sum::RangeCount;
+pub use self::uniform::Uniform;
@@ -0,0 +1,202 @@
+use num::ToPrimitive;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Exact extremes and central moments of all points inserted into a
+/// histogram, kept independently of the bins so merging bins loses nothing.
+///
+/// The moments are updated and merged with the pairwise formulas of
+/// Pébay, "Formulas for Robust, One-Pass Parallel Computation of
+/// Covariances and Arbitrary-Order Statistical Moments" (2008), which
+/// stay exact across any order of inserts and merges.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "abomonation", derive(Abomonation))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RunningMoments<V> {
+    pub(crate) min: Option<V>,
+    pub(crate) max: Option<V>,
+    pub(crate) count: f64,
+    pub(crate) mean: f64,
+    pub(crate) m2: f64,
+    pub(crate) m3: f64,
+    pub(crate) m4: f64,
+}
+
+impl<V> Default for RunningMoments<V> {
+    fn default() -> Self {
+        RunningMoments {
+            min: None,
+            max: None,
+            count: 0.,
+            mean: 0.,
+            m2: 0.,
+            m3: 0.,
+            m4: 0.,
+        }
+    }
+}
+
+impl<V: PartialOrd + Copy + ToPrimitive> RunningMoments<V> {
+    /// Add a value with the given weight
+    pub fn insert(&mut self, value: V, weight: f64) {
+        let single = RunningMoments {
+            min: Some(value),
+            max: Some(value),
+            count: weight,
+            mean: value.to_f64().unwrap_or(f64::NAN),
+            m2: 0.,
+            m3: 0.,
+            m4: 0.,
+        };
+        self.merge(&single);
+    }
+
+    /// Combine the moments of another set of points into these
+    pub fn merge(&mut self, other: &Self) {
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) if b < a => Some(b),
+            (None, b) => b,
+            (a, _) => a,
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) if b > a => Some(b),
+            (None, b) => b,
+            (a, _) => a,
+        };
+
+        let (na, nb) = (self.count, other.count);
+        let n = na + nb;
+        if nb == 0. {
+            return;
+        }
+        if na == 0. {
+            self.count = other.count;
+            self.mean = other.mean;
+            self.m2 = other.m2;
+            self.m3 = other.m3;
+            self.m4 = other.m4;
+            return;
+        }
+
+        let delta = other.mean - self.mean;
+        let delta2 = delta * delta;
+        let m2 = self.m2 + other.m2 + delta2 * na * nb / n;
+        let m3 = self.m3
+            + other.m3
+            + delta2 * delta * na * nb * (na - nb) / (n * n)
+            + 3. * delta * (na * other.m2 - nb * self.m2) / n;
+        let m4 = self.m4
+            + other.m4
+            + delta2 * delta2 * na * nb * (na * na - na * nb + nb * nb) / (n * n * n)
+            + 6. * delta2 * (na * na * other.m2 + nb * nb * self.m2) / (n * n)
+            + 4. * delta * (na * other.m3 - nb * self.m3) / n;
+
+        self.count = n;
+        self.mean += delta * nb / n;
+        self.m2 = m2;
+        self.m3 = m3;
+        self.m4 = m4;
+    }
+}
+
+impl<V> RunningMoments<V> {
+    /// Multiply the weights of all points by `factor`, e.g. to decay them.
+    /// The extremes and all normalised statistics are unchanged.
+    pub(crate) fn scale(&mut self, factor: f64) {
+        self.count *= factor;
+        self.m2 *= factor;
+        self.m3 *= factor;
+        self.m4 *= factor;
+    }
+}
+
+impl<V: Copy> RunningMoments<V> {
+    /// Smallest inserted value
+    pub fn min(&self) -> Option<V> {
+        self.min
+    }
+
+    /// Largest inserted value
+    pub fn max(&self) -> Option<V> {
+        self.max
+    }
+
+    /// Arithmetic mean of all points
+    pub fn mean(&self) -> Option<f64> {
+        if self.count > 0. {
+            Some(self.mean)
+        } else {
+            None
+        }
+    }
+
+    /// Population variance of all points
+    pub fn variance(&self) -> Option<f64> {
+        self.mean().map(|_| self.m2 / self.count)
+    }
+
+    /// Population standard deviation of all points
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    /// Skewness of all points, `None` if all points are equal
+    pub fn skewness(&self) -> Option<f64> {
+        if self.count > 0. && self.m2 > 0. {
+            Some(self.count.sqrt() * self.m3 / self.m2.powf(1.5))
+        } else {
+            None
+        }
+    }
+
+    /// Excess kurtosis of all points (zero for a normal distribution),
+    /// `None` if all points are equal
+    pub fn kurtosis(&self) -> Option<f64> {
+        if self.count > 0. && self.m2 > 0. {
+            Some(self.count * self.m4 / (self.m2 * self.m2) - 3.)
+        } else {
+            None
+        }
+    }
+}
+
+/// Exact summary statistics of the points in a histogram
+pub trait Moments<V: Copy> {
+    /// Moments of all points inserted into this histogram
+    fn moments(&self) -> &RunningMoments<V>;
+
+    /// Smallest value in this histogram
+    fn min(&self) -> Option<V> {
+        self.moments().min()
+    }
+
+    /// Largest value in this histogram
+    fn max(&self) -> Option<V> {
+        self.moments().max()
+    }
+
+    /// Arithmetic mean of the points in this histogram
+    fn mean(&self) -> Option<f64> {
+        self.moments().mean()
+    }
+
+    /// Population variance of the points in this histogram
+    fn variance(&self) -> Option<f64> {
+        self.moments().variance()
+    }
+
+    /// Population standard deviation of the points in this histogram
+    fn stddev(&self) -> Option<f64> {
+        self.moments().stddev()
+    }
+
+    /// Skewness of the points in this histogram
+    fn skewness(&self) -> Option<f64> {
+        self.moments().skewness()
+    }
+
+    /// Excess kurtosis of the points in this histogram
+    fn kurtosis(&self) -> Option<f64> {
+        self.moments().kurtosis()
+    }
+}
@@ -0,0 +1,135 @@
+use super::{DynamicHistogram, EmptyClone, Merge, MergeRef};
+use rayon::prelude::*;
+
+/// Parallel bulk insertion, available with the `rayon` feature
+pub trait ParInsertIter<T, C> {
+    /// Insert all data points, building a partial histogram for every
+    /// `chunk_size` consecutive points in parallel and merging the
+    /// partial histograms into this one.
+    ///
+    /// The result only depends on the points and `chunk_size`, not on the
+    /// number of threads. Panics if `chunk_size` is zero.
+    fn par_insert_iter(&mut self, values: &[(T, C)], chunk_size: usize);
+}
+
+impl<H, T, C> ParInsertIter<T, C> for H
+where
+    H: DynamicHistogram<T, C> + EmptyClone + Merge + Send + Sync,
+    T: Copy + Sync,
+    C: Copy + Sync,
+{
+    fn par_insert_iter(&mut self, values: &[(T, C)], chunk_size: usize) {
+        let template = self.empty_clone();
+        let partials = values
+            .par_chunks(chunk_size)
+            .map(|chunk| {
+                let mut partial = template.empty_clone();
+                partial.insert_iter(chunk);
+                partial
+            })
+            .collect();
+        if let Some(merged) = tree_merge(partials) {
+            self.merge(merged);
+        }
+    }
+}
+
+/// Parallel merging of many histograms, available with the `rayon` feature
+pub trait ParMerge<H> {
+    /// Merge all histograms into one, or `None` if there are none.
+    ///
+    /// Histograms are merged pairwise in rounds, neighbours first, so the
+    /// result does not depend on the number of threads.
+    fn par_merge(&self) -> Option<H>;
+}
+
+impl<H> ParMerge<H> for [H]
+where
+    H: EmptyClone + Merge + MergeRef + Send + Sync,
+{
+    fn par_merge(&self) -> Option<H> {
+        let pairs = self
+            .par_chunks(2)
+            .map(|pair| {
+                let mut merged = pair[0].empty_clone();
+                for histogram in pair {
+                    merged.merge_ref(histogram);
+                }
+                merged
+            })
+            .collect();
+        tree_merge(pairs)
+    }
+}
+
+/// Merge neighbouring histograms in parallel until only one is left
+fn tree_merge<H: Merge + Send>(mut histograms: Vec<H>) -> Option<H> {
+    while histograms.len() > 1 {
+        histograms = histograms
+            .into_par_iter()
+            .chunks(2)
+            .map(|pair| {
+                let mut pair = pair.into_iter();
+                let mut merged = pair.next().unwrap();
+                for histogram in pair {
+                    merged.merge(histogram);
+                }
+                merged
+            })
+            .collect();
+    }
+    histograms.pop()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use d2tree_histogram::Histogram;
+    use simple_vec_histogram::SimpleVecHistogram;
+    use traits::{MergeIter, Moments, Quantile};
+
+    #[test]
+    fn par_insert_iter() {
+        let values = (0..10_000)
+            .map(|i| (f64::from((i * 7919) % 10_000), 1u32))
+            .collect::<Vec<_>>();
+
+        let mut h = SimpleVecHistogram::new(32);
+        h.insert(-1., 1);
+        h.par_insert_iter(&values, 1000);
+        assert_eq!(h.count(), 10_001);
+        assert_eq!(h.min(), Some(-1.));
+        assert_eq!(h.max(), Some(9999.));
+        assert_eq!(h.bins().len(), 32);
+
+        // the same chunking gives the same histogram
+        let mut again = SimpleVecHistogram::new(32);
+        again.insert(-1., 1);
+        again.par_insert_iter(&values, 1000);
+        assert_eq!(again, h);
+
+        let mut tree = Histogram::new(32);
+        tree.par_insert_iter(&values, 999);
+        assert_eq!(tree.count(), 10_000);
+        SimpleVecHistogram::<f64, u32>::new(4).par_insert_iter(&[], 10);
+    }
+
+    #[test]
+    fn par_merge() {
+        let histograms = (0..9)
+            .map(|i| {
+                let mut h = SimpleVecHistogram::new(4);
+                h.insert(f64::from(i), i + 1);
+                h
+            })
+            .collect::<Vec<_>>();
+        let merged = histograms.par_merge().unwrap();
+        assert_eq!(merged.count(), 45);
+        assert_eq!(merged.quantile(1.), Some(8.));
+        assert_eq!(Some(merged), histograms.par_merge());
+
+        // a single histogram needs no merging
+        assert_eq!(histograms[..1].par_merge(), histograms[..1].iter().merge());
+        assert_eq!(histograms[..0].par_merge(), None);
+    }
+}
@@ -0,0 +1,121 @@
+use super::bins::to_f64;
+use super::{BinSummary, IterBins, Median};
+use num::{NumCast, ToPrimitive};
+
+/// Quantile and cumulative distribution estimates.
+///
+/// All estimates assume the points of a bin are spread uniformly between
+/// its `left` and `right` boundary. A bin that contains a single distinct
+/// value (`left == right`) puts all of its points at that value.
+pub trait Quantile<V> {
+    /// Estimate the value below which a fraction `q` of the data points lie.
+    ///
+    /// `quantile(0.)` is the left boundary of the first bin and `quantile(1.)`
+    /// the right boundary of the last bin. Returns `None` for an empty
+    /// histogram or if `q` is not within `[0, 1]`.
+    fn quantile(&self, q: f64) -> Option<V> {
+        self.quantiles(&[q]).pop().and_then(|value| value)
+    }
+
+    /// Estimate several quantiles in a single pass over the bins.
+    /// The results are returned in the order of `qs`.
+    fn quantiles(&self, qs: &[f64]) -> Vec<Option<V>>;
+
+    /// Estimate the fraction of data points that are less than or equal to `value`.
+    /// Returns `None` for an empty histogram.
+    fn cdf(&self, value: V) -> Option<f64>;
+}
+
+impl<H> Quantile<H::Value> for H
+where
+    H: IterBins,
+    H::Value: Copy + NumCast,
+    H::Count: Copy + ToPrimitive,
+{
+    fn quantiles(&self, qs: &[f64]) -> Vec<Option<H::Value>> {
+        let mut results = vec![None; qs.len()];
+        let total = total_count(self);
+        if total <= 0. {
+            return results;
+        }
+
+        let mut order = (0..qs.len())
+            .filter(|&i| qs[i] >= 0. && qs[i] <= 1.)
+            .collect::<Vec<_>>();
+        order.sort_by(|&a, &b| qs[a].partial_cmp(&qs[b]).unwrap());
+
+        let mut order = order.into_iter().peekable();
+        let mut cumulative = 0.;
+        let mut last = None;
+        for bin in self.iter_bins() {
+            let count = to_f64(bin.count);
+            if count <= 0. {
+                continue;
+            }
+            while let Some(&i) = order.peek() {
+                let target = qs[i] * total;
+                if target > cumulative + count {
+                    break;
+                }
+                results[i] = interpolate(&bin, (target - cumulative) / count);
+                order.next();
+            }
+            cumulative += count;
+            last = Some(bin);
+        }
+
+        // targets beyond the last bin can only stem from rounding errors
+        if let Some(bin) = last {
+            for i in order {
+                results[i] = NumCast::from(to_f64(bin.right));
+            }
+        }
+        results
+    }
+
+    fn cdf(&self, value: H::Value) -> Option<f64> {
+        let value = to_f64(value);
+        let mut total = 0.;
+        let mut below = 0.;
+        for bin in self.iter_bins() {
+            let count = to_f64(bin.count);
+            let (left, right) = (to_f64(bin.left), to_f64(bin.right));
+            total += count;
+            if right <= value {
+                below += count;
+            } else if left < value {
+                below += count * (value - left) / (right - left);
+            }
+        }
+        if total > 0. {
+            Some(below / total)
+        } else {
+            None
+        }
+    }
+}
+
+impl<H> Median<H::Value> for H
+where
+    H: IterBins,
+    H::Value: Copy + NumCast,
+    H::Count: Copy + ToPrimitive,
+{
+    fn median(&self) -> Option<H::Value> {
+        self.quantile(0.5)
+    }
+}
+
+fn total_count<H>(histogram: &H) -> f64
+where
+    H: IterBins,
+    H::Count: ToPrimitive,
+{
+    histogram.iter_bins().map(|bin| to_f64(bin.count)).sum()
+}
+
+/// Position at `ratio` between the boundaries of a bin
+fn interpolate<V: Copy + NumCast, C>(bin: &BinSummary<V, C>, ratio: f64) -> Option<V> {
+    let (left, right) = (to_f64(bin.left), to_f64(bin.right));
+    NumCast::from(left + (right - left) * ratio)
+}
@@ -0,0 +1,63 @@
+use super::bins::to_f64;
+use super::IterBins;
+use num::ToPrimitive;
+
+/// Estimates of the number of data points in a range of values, following
+/// the `sum` procedure of Ben-Haim & Tom-Tov, "A Streaming Parallel
+/// Decision Tree Algorithm" (2010).
+///
+/// Each bin is represented by its centroid `p_i = sum / count` and its
+/// count `m_i`. Half of the points of a bin are assumed to lie on each side
+/// of its centroid, and the density between two neighbouring centroids is
+/// interpolated linearly (a trapezoid between `m_i` and `m_{i+1}`).
+pub trait RangeCount<V> {
+    /// Estimate the number of data points less than or equal to `value`.
+    ///
+    /// Values below the first centroid yield `0`, values at or above the last
+    /// centroid yield the total count.
+    fn sum_upto(&self, value: V) -> f64;
+
+    /// Estimate the number of data points in the interval `(a, b]`.
+    /// Returns `0` if `b <= a`.
+    fn count_between(&self, a: V, b: V) -> f64 {
+        (self.sum_upto(b) - self.sum_upto(a)).max(0.)
+    }
+}
+
+impl<H> RangeCount<H::Value> for H
+where
+    H: IterBins,
+    H::Value: ToPrimitive,
+    H::Count: ToPrimitive,
+{
+    fn sum_upto(&self, value: H::Value) -> f64 {
+        let b = to_f64(value);
+        let mut sum = 0.;
+        let mut previous: Option<(f64, f64)> = None;
+
+        for bin in self.iter_bins() {
+            let m = to_f64(bin.count);
+            if m <= 0. {
+                continue;
+            }
+            let p = to_f64(bin.sum) / m;
+            if b < p {
+                // b lies between the previous centroid and this one
+                return match previous {
+                    Some((p_i, m_i)) => {
+                        let ratio = (b - p_i) / (p - p_i);
+                        let m_b = m_i + (m - m_i) * ratio;
+                        sum + m_i / 2. + (m_i + m_b) / 2. * ratio
+                    }
+                    None => 0.,
+                };
+            }
+            if let Some((_, m_i)) = previous {
+                sum += m_i;
+            }
+            previous = Some((p, m));
+        }
+
+        previous.map_or(0., |(_, m_i)| sum + m_i)
+    }
+}
@@ -0,0 +1,75 @@
+use super::bins::to_f64;
+use super::IterBins;
+use num::{NumCast, ToPrimitive};
+
+/// Equi-depth split points, following the `uniform` procedure of
+/// Ben-Haim & Tom-Tov, "A Streaming Parallel Decision Tree Algorithm" (2010).
+pub trait Uniform<V> {
+    /// Return `n_splits - 1` points that split the data into `n_splits`
+    /// intervals containing roughly the same number of points each.
+    ///
+    /// The points are estimated with the same trapezoid interpolation between
+    /// bin centroids as `RangeCount::sum_upto`, so `sum_upto(u_j)` is close to
+    /// `j / n_splits` of the total count. Points whose target count lies
+    /// before the first or after the last centroid are clamped to that
+    /// centroid. Returns no points for an empty histogram or `n_splits < 2`.
+    fn uniform(&self, n_splits: usize) -> Vec<V>;
+}
+
+impl<H> Uniform<H::Value> for H
+where
+    H: IterBins,
+    H::Value: NumCast,
+    H::Count: ToPrimitive,
+{
+    fn uniform(&self, n_splits: usize) -> Vec<H::Value> {
+        // (centroid, count) of every non-empty bin
+        let centroids = self
+            .iter_bins()
+            .map(|bin| (to_f64(bin.sum), to_f64(bin.count)))
+            .filter(|&(_, m)| m > 0.)
+            .map(|(sum, m)| (sum / m, m))
+            .collect::<Vec<_>>();
+        if centroids.is_empty() || n_splits < 2 {
+            return Vec::new();
+        }
+        let total: f64 = centroids.iter().map(|&(_, m)| m).sum();
+
+        let mut points = Vec::with_capacity(n_splits - 1);
+        // index of the centroid left of the current target and the
+        // estimated count of points up to that centroid
+        let mut i = 0;
+        let mut sum_at_i = centroids[0].1 / 2.;
+        for j in 1..n_splits {
+            let s = total * j as f64 / n_splits as f64;
+            while i + 1 < centroids.len() {
+                let sum_at_next = sum_at_i + (centroids[i].1 + centroids[i + 1].1) / 2.;
+                if sum_at_next > s {
+                    break;
+                }
+                sum_at_i = sum_at_next;
+                i += 1;
+            }
+
+            let (p_i, m_i) = centroids[i];
+            let point = if s <= sum_at_i || i + 1 == centroids.len() {
+                p_i
+            } else {
+                let (p_next, m_next) = centroids[i + 1];
+                // solve the trapezoid area for the relative position z
+                let d = s - sum_at_i;
+                let a = m_next - m_i;
+                let b = 2. * m_i;
+                let c = -2. * d;
+                let z = if a == 0. {
+                    -c / b
+                } else {
+                    (-b + (b * b - 4. * a * c).sqrt()) / (2. * a)
+                };
+                p_i + (p_next - p_i) * z
+            };
+            points.extend(NumCast::from(point));
+        }
+        points
+    }
+}
@@ -0,0 +1,62 @@
+use num::traits::NumAssign;
+use num::{Bounded, ToPrimitive};
+use std::cmp::Ordering;
+
+/// Largest bin cap of a deserialized or decoded histogram. Larger caps are
+/// rejected, as no real histogram needs them and the allocations and
+/// arithmetic sized by the cap would overflow.
+pub const MAX_BINS: usize = 1 << 24;
+
+/// Check that the boundaries and counts of deserialized bins describe a
+/// valid histogram: a bin cap of at most `MAX_BINS`, no more bins than the
+/// bin cap, no bin with its left boundary above its right one, bins sorted
+/// by their left boundary, only positive counts and a total count that fits
+/// into the count type. NaN boundaries and counts fail every comparison and
+/// are rejected as well.
+pub fn check_bins<V, C>(
+    bins: impl IntoIterator<Item = (V, V, C)>,
+    n_bins: usize,
+) -> Result<(), &'static str>
+where
+    V: PartialOrd,
+    C: Copy + PartialOrd + Bounded + NumAssign + ToPrimitive,
+{
+    if n_bins > MAX_BINS {
+        return Err("bin cap above the maximum number of bins");
+    }
+    let mut len = 0;
+    let mut previous_left: Option<V> = None;
+    let mut total = C::zero();
+    for (left, right, count) in bins {
+        if !less_or_equal(&left, &right) {
+            return Err("bin has a left boundary greater than its right boundary");
+        }
+        match count.to_f64() {
+            Some(weight) if weight > 0. => {}
+            _ => return Err("bin has a count that is not positive"),
+        }
+        if count > C::max_value() - total {
+            return Err("total count overflows the count type");
+        }
+        total += count;
+        if let Some(previous_left) = previous_left {
+            if !less_or_equal(&previous_left, &left) {
+                return Err("bins are not sorted by their left boundary");
+            }
+        }
+        previous_left = Some(left);
+        len += 1;
+    }
+    // a histogram always keeps at least one bin, even with a bin cap of zero
+    if len > n_bins.max(1) {
+        return Err("more bins than the maximum number of bins");
+    }
+    Ok(())
+}
+
+fn less_or_equal<V: PartialOrd>(a: &V, b: &V) -> bool {
+    matches!(
+        a.partial_cmp(b),
+        Some(Ordering::Less) | Some(Ordering::Equal)
+    )
+}
@@ -0,0 +1,250 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use traits::{DynamicHistogram, EmptyClone, Median, MergeIter, MergeRef, Quantile};
+
+/// Source of the current time for a [`WindowedHistogram`]
+pub trait Clock {
+    /// Time elapsed since an arbitrary but fixed origin
+    fn now(&self) -> Duration;
+}
+
+impl<K: Clock> Clock for &K {
+    fn now(&self) -> Duration {
+        (*self).now()
+    }
+}
+
+/// Clock reading the system time, relative to the Unix epoch
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+}
+
+/// Clock that only moves when told to, e.g. for deterministic tests
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    now: Cell<Duration>,
+}
+
+impl ManualClock {
+    pub fn new(now: Duration) -> Self {
+        ManualClock {
+            now: Cell::new(now),
+        }
+    }
+
+    /// Move this clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+
+    /// Set this clock to `now`
+    pub fn set(&self, now: Duration) {
+        self.now.set(now);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+/// Histogram over the data points of the last `n_slots` time slots of
+/// `slot_width` each, including the current one.
+///
+/// Points are inserted into one histogram per slot, kept in a ring that
+/// drops the slots that fell out of the window as time advances. Queries
+/// merge the histograms of the slots in the window, so memory stays
+/// bounded by `n_slots` histograms.
+#[derive(Clone, Debug)]
+pub struct WindowedHistogram<H, K = SystemClock> {
+    /// Histograms with the index of their slot, oldest first
+    slots: VecDeque<(u64, H)>,
+    n_slots: usize,
+    slot_width: Duration,
+    template: H,
+    clock: K,
+}
+
+impl<H: EmptyClone> WindowedHistogram<H> {
+    /// Instantiate a window over the last `n_slots` slots of the system
+    /// time, creating the histogram of each slot as an empty clone of
+    /// `template`.
+    ///
+    /// Panics if `n_slots` or `slot_width` is zero.
+    pub fn new(template: H, n_slots: usize, slot_width: Duration) -> Self {
+        Self::with_clock(template, n_slots, slot_width, SystemClock)
+    }
+}
+
+impl<H: EmptyClone, K: Clock> WindowedHistogram<H, K> {
+    /// Instantiate a window over the last `n_slots` slots of the time
+    /// read from `clock`, creating the histogram of each slot as an empty
+    /// clone of `template`.
+    ///
+    /// Panics if `n_slots` or `slot_width` is zero.
+    pub fn with_clock(template: H, n_slots: usize, slot_width: Duration, clock: K) -> Self {
+        assert!(n_slots > 0, "window must have at least one slot");
+        assert!(
+            slot_width > Duration::default(),
+            "slot width must be positive"
+        );
+        WindowedHistogram {
+            slots: VecDeque::with_capacity(n_slots),
+            n_slots,
+            slot_width,
+            template: template.empty_clone(),
+            clock,
+        }
+    }
+
+    /// Index of the slot of the current time
+    fn current_slot(&self) -> u64 {
+        (self.clock.now().as_nanos() / self.slot_width.as_nanos()) as u64
+    }
+
+    /// Whether the slot with the given index is in the window ending at `current`
+    fn is_live(&self, slot: u64, current: u64) -> bool {
+        slot + self.n_slots as u64 > current
+    }
+
+    /// Histograms of the slots in the current window, oldest first
+    pub fn live_slots(&self) -> impl Iterator<Item = &H> + '_ {
+        let current = self.current_slot();
+        self.slots
+            .iter()
+            .filter(move |(slot, _)| self.is_live(*slot, current))
+            .map(|(_, histogram)| histogram)
+    }
+
+    /// Histogram of the current slot, dropping the slots that fell out of
+    /// the window
+    pub fn current_mut(&mut self) -> &mut H {
+        let current = self.current_slot();
+        while let Some(&(slot, _)) = self.slots.front() {
+            if self.is_live(slot, current) {
+                break;
+            }
+            self.slots.pop_front();
+        }
+        // a clock going backwards keeps filling the newest slot
+        let is_current = self.slots.back().is_some_and(|(slot, _)| *slot >= current);
+        if !is_current {
+            self.slots.push_back((current, self.template.empty_clone()));
+        }
+        &mut self.slots.back_mut().unwrap().1
+    }
+
+    /// Insert a new data point into the histogram of the current slot
+    pub fn insert<V, C>(&mut self, value: V, count: C)
+    where
+        H: DynamicHistogram<V, C>,
+    {
+        self.current_mut().insert(value, count)
+    }
+
+    /// Merge the histograms of all slots in the current window
+    pub fn merged(&self) -> H
+    where
+        H: MergeRef,
+    {
+        self.live_slots()
+            .merge()
+            .unwrap_or_else(|| self.template.empty_clone())
+    }
+
+    /// Estimate the `q`-quantile of the points in the current window
+    pub fn quantile<V>(&self, q: f64) -> Option<V>
+    where
+        H: MergeRef + Quantile<V>,
+    {
+        self.merged().quantile(q)
+    }
+
+    /// Estimate the median of the points in the current window
+    pub fn median<V>(&self) -> Option<V>
+    where
+        H: MergeRef + Median<V>,
+    {
+        self.merged().median()
+    }
+
+    /// Total count of the points in the current window
+    pub fn count<V, C>(&self) -> C
+    where
+        H: MergeRef + DynamicHistogram<V, C>,
+    {
+        self.merged().count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use d2tree_histogram::Histogram;
+    use simple_vec_histogram::SimpleVecHistogram;
+
+    fn millis(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn sliding_window() {
+        let clock = ManualClock::default();
+        let mut window =
+            WindowedHistogram::with_clock(SimpleVecHistogram::new(8), 3, millis(1000), &clock);
+        assert_eq!(window.count(), 0u32);
+        assert_eq!(window.median(), None::<f64>);
+
+        window.insert(1., 1u32);
+        clock.set(millis(1500));
+        window.insert(2., 1);
+        clock.set(millis(2999));
+        window.insert(3., 2);
+        assert_eq!(window.count(), 4);
+        assert_eq!(window.quantile(0.), Some(1.));
+
+        // the first slot leaves the window without any insert
+        clock.set(millis(3000));
+        assert_eq!(window.count(), 3);
+        assert_eq!(window.quantile(0.), Some(2.));
+        assert_eq!(window.median(), Some(3.));
+
+        clock.advance(millis(2000));
+        window.insert(4., 1);
+        assert_eq!(window.slots.len(), 1);
+        assert_eq!(window.count(), 1);
+
+        // a clock going backwards inserts into the newest slot
+        clock.set(millis(0));
+        window.insert(5., 1);
+        assert_eq!(window.slots.len(), 1);
+    }
+
+    #[test]
+    fn tree_histogram_slots() {
+        let clock = ManualClock::new(millis(10_000));
+        let mut window =
+            WindowedHistogram::with_clock(Histogram::new(4), 2, millis(60_000), &clock);
+        for i in 0..10 {
+            window.insert(f64::from(i), 1u32);
+            clock.advance(millis(10_000));
+        }
+        assert_eq!(window.count(), 10);
+
+        // the slot with the first five points leaves the window
+        clock.advance(millis(10_000));
+        assert_eq!(window.live_slots().count(), 1);
+        assert_eq!(window.count(), 5);
+        assert_eq!(window.quantile(0.), Some(5.));
+        assert_eq!(window.quantile(1.), Some(9.));
+    }
+}
@@ -0,0 +1,639 @@
+//! Compact, versioned binary encoding of histograms, used by
+//! [`BinaryEncoding`](../traits/trait.BinaryEncoding.html).
+//!
+//! All fixed-width numbers are little endian. Varints are unsigned LEB128,
+//! seven bits per byte with the high bit set on all but the last byte.
+//! A payload of format version 1 is laid out as follows:
+//!
+//! | field          | encoding                                           |
+//! |----------------|----------------------------------------------------|
+//! | version        | `u8`, currently `1`                                |
+//! | flags          | `u8`, see below                                    |
+//! | value type     | `u8` tag of the value type, see [`WireNumber`]     |
+//! | count type     | `u8` tag of the count type                         |
+//! | bin cap        | varint                                             |
+//! | number of bins | varint                                             |
+//! | moments        | count, mean, m2, m3 and m4 as `f64`                |
+//! | extremes       | min and max as values, only if `EXTREMES` is set   |
+//! | non-finite     | NaN, +inf and -inf counts if `NON_FINITE` is set   |
+//! | bins           | left, right, count, sum and sum of squares per bin |
+//!
+//! Values are written with the fixed width of their type. Counts of unsigned
+//! integer types are varints, all other counts use their fixed width.
+//!
+//! Flags:
+//!
+//! - `0x01` (`DELTA`): bin boundaries are delta encoded. The left boundary of
+//!   a bin is stored relative to the left boundary of the previous bin (zero
+//!   for the first bin) and the right boundary relative to the left boundary
+//!   of the same bin. A delta is the wrapping difference of the bit patterns
+//!   of both values, zigzag encoded as a varint, so it is lossless for every
+//!   value including floats. Encoders set this flag when it makes the payload
+//!   smaller, which it does for the typical case of narrow, close bins.
+//! - `0x02` (`EXTREMES`): the exact minimum and maximum are present.
+//! - `0x04` (`NON_FINITE`): the counts of the NaN and infinite values left
+//!   out of the bins are present. Encoders set this flag when any of them is
+//!   not zero.
+//!
+//! Decoders reject payloads with an unknown version, unknown flags or type
+//! tags other than the requested ones. They also reject payloads that no
+//! histogram could have written: a bin cap above
+//! [`MAX_BINS`](../constant.MAX_BINS.html), invalid bins, moments that are
+//! not finite or negative where they can't be, or non-finite counts that
+//! are negative or not finite. The merge strategy and the policy for
+//! non-finite values of a histogram are not part of the payload.
+
+use num::traits::NumAssign;
+use num::{Bounded, ToPrimitive};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use traits::{BinSummary, RunningMoments};
+use validation::check_bins;
+
+/// Version of the format written by this crate
+pub const VERSION: u8 = 1;
+
+const DELTA: u8 = 0x01;
+const EXTREMES: u8 = 0x02;
+const NON_FINITE: u8 = 0x04;
+
+/// Error returned when a binary histogram payload can't be decoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The payload ended before the histogram was complete
+    Truncated,
+    /// The payload uses a format version this crate can't read
+    UnsupportedVersion(u8),
+    /// The payload stores values of another type, identified by its tag
+    ValueTypeMismatch { expected: u8, found: u8 },
+    /// The payload stores counts of another type, identified by its tag
+    CountTypeMismatch { expected: u8, found: u8 },
+    /// The payload is malformed or describes an invalid histogram
+    Invalid(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Truncated => write!(fmt, "histogram payload is truncated"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(fmt, "unsupported histogram format version {}", version)
+            }
+            DecodeError::ValueTypeMismatch { expected, found } => {
+                write!(fmt, "expected value type tag {}, found {}", expected, found)
+            }
+            DecodeError::CountTypeMismatch { expected, found } => {
+                write!(fmt, "expected count type tag {}, found {}", expected, found)
+            }
+            DecodeError::Invalid(reason) => write!(fmt, "invalid histogram payload: {}", reason),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// A number that can be written to the binary format
+pub trait WireNumber: Copy {
+    /// Tag identifying this type in the payload header
+    const TAG: u8;
+    /// Number of bytes of the fixed-width encoding
+    const WIDTH: usize;
+    /// Whether counts of this type are written as varints
+    const VARINT: bool;
+
+    /// Bit pattern of this number, zero-extended to 64 bits
+    fn to_bits(self) -> u64;
+
+    /// Recover a number from its bit pattern, if it fits this type
+    fn from_bits(bits: u64) -> Option<Self>;
+}
+
+macro_rules! wire_integer {
+    ($t:ty, $bits:ty, $tag:expr, $varint:expr) => {
+        impl WireNumber for $t {
+            const TAG: u8 = $tag;
+            const WIDTH: usize = ::std::mem::size_of::<$t>();
+            const VARINT: bool = $varint;
+
+            fn to_bits(self) -> u64 {
+                self as $bits as u64
+            }
+
+            fn from_bits(bits: u64) -> Option<Self> {
+                if bits <= <$bits>::MAX as u64 {
+                    Some(bits as $bits as $t)
+                } else {
+                    None
+                }
+            }
+        }
+    };
+}
+
+wire_integer!(u8, u8, 1, true);
+wire_integer!(u16, u16, 2, true);
+wire_integer!(u32, u32, 3, true);
+wire_integer!(u64, u64, 4, true);
+wire_integer!(usize, usize, 5, true);
+wire_integer!(i32, u32, 6, false);
+wire_integer!(i64, u64, 7, false);
+
+impl WireNumber for f32 {
+    const TAG: u8 = 8;
+    const WIDTH: usize = 4;
+    const VARINT: bool = false;
+
+    fn to_bits(self) -> u64 {
+        u64::from(f32::to_bits(self))
+    }
+
+    fn from_bits(bits: u64) -> Option<Self> {
+        if bits <= u64::from(u32::MAX) {
+            Some(f32::from_bits(bits as u32))
+        } else {
+            None
+        }
+    }
+}
+
+impl WireNumber for f64 {
+    const TAG: u8 = 9;
+    const WIDTH: usize = 8;
+    const VARINT: bool = false;
+
+    fn to_bits(self) -> u64 {
+        f64::to_bits(self)
+    }
+
+    fn from_bits(bits: u64) -> Option<Self> {
+        Some(f64::from_bits(bits))
+    }
+}
+
+/// Contents of a decoded payload
+pub(crate) struct Payload<V, C> {
+    pub n_bins: usize,
+    pub bins: Vec<BinSummary<V, C>>,
+    pub moments: RunningMoments<V>,
+    pub non_finite: Option<[C; 3]>,
+}
+
+/// Encode the bins, moments and non-finite counts of a histogram with a
+/// bin cap of `n_bins`
+pub(crate) fn encode<V: WireNumber, C: WireNumber>(
+    n_bins: usize,
+    bins: &[BinSummary<V, C>],
+    moments: &RunningMoments<V>,
+    non_finite: Option<[C; 3]>,
+) -> Vec<u8> {
+    let raw_len = 2 * V::WIDTH * bins.len();
+    let delta_len: usize = boundary_deltas(bins)
+        .map(|(left, right)| varint_len(left) + varint_len(right))
+        .sum();
+    let mut flags = 0;
+    if delta_len < raw_len {
+        flags |= DELTA;
+    }
+    let extremes = match (moments.min, moments.max) {
+        (Some(min), Some(max)) => {
+            flags |= EXTREMES;
+            Some((min, max))
+        }
+        _ => None,
+    };
+    if non_finite.is_some() {
+        flags |= NON_FINITE;
+    }
+
+    let mut out = Vec::with_capacity(32 + raw_len.min(delta_len) + 3 * 8 * bins.len());
+    out.extend_from_slice(&[VERSION, flags, V::TAG, C::TAG]);
+    write_varint(&mut out, n_bins as u64);
+    write_varint(&mut out, bins.len() as u64);
+    for moment in &[
+        moments.count,
+        moments.mean,
+        moments.m2,
+        moments.m3,
+        moments.m4,
+    ] {
+        write_fixed(&mut out, *moment);
+    }
+    if let Some((min, max)) = extremes {
+        write_fixed(&mut out, min);
+        write_fixed(&mut out, max);
+    }
+    for count in non_finite.iter().flatten() {
+        write_count(&mut out, *count);
+    }
+
+    let mut deltas = boundary_deltas(bins);
+    for bin in bins {
+        match deltas.next() {
+            Some((left, right)) if flags & DELTA != 0 => {
+                write_varint(&mut out, left);
+                write_varint(&mut out, right);
+            }
+            _ => {
+                write_fixed(&mut out, bin.left);
+                write_fixed(&mut out, bin.right);
+            }
+        }
+        write_count(&mut out, bin.count);
+        write_fixed(&mut out, bin.sum);
+        write_fixed(&mut out, bin.sum_sq);
+    }
+    out
+}
+
+/// Decode a payload, checking its header against the requested types
+/// and the bins against the invariants of a histogram
+pub(crate) fn decode<V, C>(bytes: &[u8]) -> Result<Payload<V, C>, DecodeError>
+where
+    V: WireNumber + PartialOrd,
+    C: WireNumber + PartialOrd + Bounded + NumAssign + ToPrimitive,
+{
+    let mut reader = Reader { bytes };
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let flags = reader.byte()?;
+    if flags & !(DELTA | EXTREMES | NON_FINITE) != 0 {
+        return Err(DecodeError::Invalid("unknown flags"));
+    }
+    let value_tag = reader.byte()?;
+    if value_tag != V::TAG {
+        return Err(DecodeError::ValueTypeMismatch {
+            expected: V::TAG,
+            found: value_tag,
+        });
+    }
+    let count_tag = reader.byte()?;
+    if count_tag != C::TAG {
+        return Err(DecodeError::CountTypeMismatch {
+            expected: C::TAG,
+            found: count_tag,
+        });
+    }
+    let n_bins = reader.length()?;
+    let len = reader.length()?;
+
+    let mut moments = RunningMoments {
+        min: None,
+        max: None,
+        count: reader.fixed()?,
+        mean: reader.fixed()?,
+        m2: reader.fixed()?,
+        m3: reader.fixed()?,
+        m4: reader.fixed()?,
+    };
+    if flags & EXTREMES != 0 {
+        moments.min = Some(reader.fixed()?);
+        moments.max = Some(reader.fixed()?);
+    }
+    check_moments(&moments).map_err(DecodeError::Invalid)?;
+    let non_finite: Option<[C; 3]> = if flags & NON_FINITE != 0 {
+        Some([reader.count()?, reader.count()?, reader.count()?])
+    } else {
+        None
+    };
+    let mut counts = non_finite.iter().flatten();
+    if !counts.all(|count| count.to_f64().is_some_and(|c| c >= 0. && c.is_finite())) {
+        return Err(DecodeError::Invalid(
+            "non-finite count is negative or not finite",
+        ));
+    }
+
+    // don't trust the length for the allocation, a bin takes at least 4 bytes
+    let mut bins = Vec::with_capacity(len.min(reader.bytes.len() / 4));
+    let mut previous_left = 0u64;
+    for _ in 0..len {
+        let (left, right) = if flags & DELTA != 0 {
+            let left = previous_left.wrapping_add(unzigzag(reader.varint()?));
+            let right = left.wrapping_add(unzigzag(reader.varint()?));
+            previous_left = left;
+            (number(left)?, number(right)?)
+        } else {
+            (reader.fixed()?, reader.fixed()?)
+        };
+        bins.push(BinSummary {
+            left,
+            right,
+            count: reader.count()?,
+            sum: reader.fixed()?,
+            sum_sq: reader.fixed()?,
+        });
+    }
+    if !reader.bytes.is_empty() {
+        return Err(DecodeError::Invalid("trailing bytes after the last bin"));
+    }
+
+    let bounds = bins.iter().map(|b| (b.left, b.right, b.count));
+    check_bins(bounds, n_bins).map_err(DecodeError::Invalid)?;
+    Ok(Payload {
+        n_bins,
+        bins,
+        moments,
+        non_finite,
+    })
+}
+
+/// Check that decoded moments could have come from a histogram: finite
+/// moments, a non-negative count and even central moments, and a minimum
+/// not above the maximum
+fn check_moments<V: PartialOrd>(moments: &RunningMoments<V>) -> Result<(), &'static str> {
+    let RunningMoments {
+        count,
+        mean,
+        m2,
+        m3,
+        m4,
+        ..
+    } = *moments;
+    if ![count, mean, m2, m3, m4].iter().all(|m| m.is_finite()) {
+        return Err("moments are not finite");
+    }
+    if count < 0. || m2 < 0. || m4 < 0. {
+        return Err("moments are negative");
+    }
+    if let (Some(min), Some(max)) = (&moments.min, &moments.max) {
+        if matches!(min.partial_cmp(max), None | Some(Ordering::Greater)) {
+            return Err("minimum is greater than the maximum");
+        }
+    }
+    Ok(())
+}
+
+/// Zigzag encoded deltas of the bin boundaries, as described in the module docs
+fn boundary_deltas<'a, V: WireNumber, C>(
+    bins: &'a [BinSummary<V, C>],
+) -> impl Iterator<Item = (u64, u64)> + 'a {
+    let mut previous_left = 0u64;
+    bins.iter().map(move |bin| {
+        let (left, right) = (bin.left.to_bits(), bin.right.to_bits());
+        let deltas = (
+            zigzag(left.wrapping_sub(previous_left)),
+            zigzag(right.wrapping_sub(left)),
+        );
+        previous_left = left;
+        deltas
+    })
+}
+
+fn zigzag(delta: u64) -> u64 {
+    (delta << 1) ^ ((delta as i64 >> 63) as u64)
+}
+
+fn unzigzag(encoded: u64) -> u64 {
+    (encoded >> 1) ^ (encoded & 1).wrapping_neg()
+}
+
+fn varint_len(mut value: u64) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_fixed<N: WireNumber>(out: &mut Vec<u8>, number: N) {
+    out.extend_from_slice(&number.to_bits().to_le_bytes()[..N::WIDTH]);
+}
+
+/// Write a count in the encoding of its type
+fn write_count<C: WireNumber>(out: &mut Vec<u8>, count: C) {
+    if C::VARINT {
+        write_varint(out, count.to_bits());
+    } else {
+        write_fixed(out, count);
+    }
+}
+
+fn number<N: WireNumber>(bits: u64) -> Result<N, DecodeError> {
+    N::from_bits(bits).ok_or(DecodeError::Invalid("number out of range for its type"))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn fixed<N: WireNumber>(&mut self) -> Result<N, DecodeError> {
+        let mut bits = [0; 8];
+        bits[..N::WIDTH].copy_from_slice(self.take(N::WIDTH)?);
+        number(u64::from_le_bytes(bits))
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            if shift == 63 && byte > 1 {
+                return Err(DecodeError::Invalid("varint overflows 64 bits"));
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::Invalid("varint overflows 64 bits"))
+    }
+
+    fn count<C: WireNumber>(&mut self) -> Result<C, DecodeError> {
+        if C::VARINT {
+            number(self.varint()?)
+        } else {
+            self.fixed()
+        }
+    }
+
+    fn length(&mut self) -> Result<usize, DecodeError> {
+        number(self.varint()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_vec_histogram::SimpleVecHistogram;
+    use traits::{BinaryEncoding, DynamicHistogram};
+
+    fn histogram(n_bins: usize, values: &[f64]) -> SimpleVecHistogram<f64, u32> {
+        let mut h = SimpleVecHistogram::new(n_bins);
+        for v in values {
+            h.insert(*v, 1);
+        }
+        h
+    }
+
+    #[test]
+    fn delta_encoding() {
+        // close, narrow bins are stored as deltas
+        let close = histogram(4, &[1., 1., 1.5, 2., 2.25]).to_bytes();
+        assert_eq!(close[1] & DELTA, DELTA);
+        let restored = SimpleVecHistogram::<f64, u32>::from_bytes(&close).unwrap();
+        assert_eq!(restored, histogram(4, &[1., 1., 1.5, 2., 2.25]));
+
+        // a wide bin with unrelated bit patterns is cheaper as plain values
+        let spread = histogram(1, &[-1e50, 1e50]).to_bytes();
+        assert_eq!(spread[1] & DELTA, 0);
+        let restored = SimpleVecHistogram::<f64, u32>::from_bytes(&spread).unwrap();
+        assert_eq!(restored, histogram(1, &[-1e50, 1e50]));
+
+        for delta in &[0, 1, u64::MAX, 1 << 63, 12345] {
+            assert_eq!(unzigzag(zigzag(*delta)), *delta);
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let bytes = histogram(4, &[1., 2., 3.]).to_bytes();
+        for len in 0..bytes.len() {
+            assert_eq!(
+                SimpleVecHistogram::<f64, u32>::from_bytes(&bytes[..len]),
+                Err(DecodeError::Truncated)
+            );
+        }
+
+        let mut future = bytes.clone();
+        future[0] = VERSION + 1;
+        assert_eq!(
+            SimpleVecHistogram::<f64, u32>::from_bytes(&future),
+            Err(DecodeError::UnsupportedVersion(VERSION + 1))
+        );
+        assert_eq!(
+            SimpleVecHistogram::<f32, u32>::from_bytes(&bytes),
+            Err(DecodeError::ValueTypeMismatch {
+                expected: 8,
+                found: 9
+            })
+        );
+        assert_eq!(
+            SimpleVecHistogram::<f64, u64>::from_bytes(&bytes),
+            Err(DecodeError::CountTypeMismatch {
+                expected: 4,
+                found: 3
+            })
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(SimpleVecHistogram::<f64, u32>::from_bytes(&trailing).is_err());
+
+        // a bin cap of 2 for 3 bins
+        let mut over_cap = bytes.clone();
+        over_cap[4] = 2;
+        assert_eq!(
+            SimpleVecHistogram::<f64, u32>::from_bytes(&over_cap),
+            Err(DecodeError::Invalid(
+                "more bins than the maximum number of bins"
+            ))
+        );
+
+        // counts that overflow the total or are not positive
+        let bin = |left, count| BinSummary {
+            left,
+            right: left,
+            count,
+            sum: left * f64::from(count),
+            sum_sq: left * left * f64::from(count),
+        };
+        let moments = RunningMoments::default();
+        let overflow = encode(4, &[bin(1., 200u8), bin(2., 200)], &moments, None);
+        assert_eq!(
+            SimpleVecHistogram::<f64, u8>::from_bytes(&overflow),
+            Err(DecodeError::Invalid("total count overflows the count type"))
+        );
+        let empty_bin = encode(4, &[bin(1., 0u8), bin(2., 1)], &moments, None);
+        assert_eq!(
+            SimpleVecHistogram::<f64, u8>::from_bytes(&empty_bin),
+            Err(DecodeError::Invalid("bin has a count that is not positive"))
+        );
+
+        // forged caps, moments and non-finite counts
+        let invalid = |bytes: Vec<u8>| decode::<f64, u8>(&bytes).err();
+        let bins = [bin(1., 2), bin(2., 1)];
+        assert!(invalid(encode(::MAX_BINS, &bins, &moments, None)).is_none());
+        assert_eq!(
+            invalid(encode(1 << 62, &bins, &moments, None)),
+            Some(DecodeError::Invalid(
+                "bin cap above the maximum number of bins"
+            ))
+        );
+        let with = |change: fn(&mut RunningMoments<f64>)| {
+            let mut moments = RunningMoments::default();
+            moments.insert(1., 2.);
+            moments.insert(2., 1.);
+            change(&mut moments);
+            encode(4, &bins, &moments, None)
+        };
+        assert!(invalid(with(|_| {})).is_none());
+        for change in &[
+            |m: &mut RunningMoments<f64>| m.count = f64::NAN,
+            |m: &mut RunningMoments<f64>| m.mean = f64::INFINITY,
+            |m: &mut RunningMoments<f64>| m.m3 = f64::NEG_INFINITY,
+        ] {
+            assert_eq!(
+                invalid(with(*change)),
+                Some(DecodeError::Invalid("moments are not finite"))
+            );
+        }
+        for change in &[
+            |m: &mut RunningMoments<f64>| m.count = -3.,
+            |m: &mut RunningMoments<f64>| m.m2 = -1.,
+            |m: &mut RunningMoments<f64>| m.m4 = -1.,
+        ] {
+            assert_eq!(
+                invalid(with(*change)),
+                Some(DecodeError::Invalid("moments are negative"))
+            );
+        }
+        assert_eq!(
+            invalid(with(|m| m.min = Some(3.))),
+            Some(DecodeError::Invalid("minimum is greater than the maximum"))
+        );
+        assert_eq!(
+            invalid(with(|m| m.max = Some(f64::NAN))),
+            Some(DecodeError::Invalid("minimum is greater than the maximum"))
+        );
+        assert!(invalid(encode(4, &bins, &moments, Some([1, 0, 2]))).is_none());
+        let no_bins: &[BinSummary<f64, i32>] = &[];
+        assert_eq!(
+            decode::<f64, i32>(&encode(4, no_bins, &moments, Some([1, -1, 2]))).err(),
+            Some(DecodeError::Invalid(
+                "non-finite count is negative or not finite"
+            ))
+        );
+        let no_bins: &[BinSummary<f64, f64>] = &[];
+        let nan_count = encode(4, no_bins, &moments, Some([0., f64::NAN, 0.]));
+        assert_eq!(
+            decode::<f64, f64>(&nan_count).err(),
+            Some(DecodeError::Invalid(
+                "non-finite count is negative or not finite"
+            ))
+        );
+    }
+}